@@ -1,3 +1,8 @@
 fn main() {
-    println!("cargo:rustc-link-lib=advapi32");
+    // the ASIO host links against the registry API to enumerate drivers
+    let windows = std::env::var_os("CARGO_CFG_WINDOWS").is_some();
+    let asio = std::env::var_os("CARGO_FEATURE_ASIO").is_some();
+    if windows && asio {
+        println!("cargo:rustc-link-lib=advapi32");
+    }
 }
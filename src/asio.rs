@@ -1,19 +1,57 @@
+use crate::asio_sample::{AsioSampleType, Decode, Encode};
+use crate::backend::{AudioSink, AudioSource};
+use crate::channel_map::ChannelMap;
+use crate::devices::{AsioDriverInfo, BufferSizeRange};
+use crate::ring::{FrameRingConsumer, FrameRingProducer};
+use crate::timestamp::{new_timestamp_queue, Timestamp, TimestampConsumer, TimestampProducer};
 use crate::util::*;
-use crate::{ring::FrameRingProducer, visualizer};
+use crate::{selector::DriverSelector, visualizer};
 use asio_sys::{
     asio_import::{
-        ASIOBufferInfo, ASIOCallbacks, ASIOChannelInfo, ASIOCreateBuffers, ASIODriverInfo,
-        ASIOGetBufferSize, ASIOGetChannelInfo, ASIOGetChannels, ASIOInit, ASIOSampleRate,
-        ASIOStart, ASIOTime, AsioDrivers,
+        can_sample_rate, get_sample_rate, remove_current_driver, set_sample_rate, ASIOBufferInfo,
+        ASIOCallbacks, ASIOChannelInfo, ASIOCreateBuffers, ASIODisposeBuffers, ASIODriverInfo,
+        ASIOExit, ASIOGetBufferSize, ASIOGetChannelInfo, ASIOGetChannels, ASIOGetSamplePosition,
+        ASIOInit, ASIOOutputReady, ASIOSampleRate, ASIOSamples, ASIOStart, ASIOStop, ASIOTime,
+        ASIOTimeStamp, AsioDrivers,
     },
     errors::AsioErrorWrapper,
+    AsioMessageSelectors, AsioTime,
+};
+use std::{
+    ptr,
+    sync::{
+        atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering},
+        Arc,
+    },
 };
-use std::{ptr, sync::Arc};
 
 static mut RING: Option<FrameRingProducer> = None;
+/// Driver clock at each switch, next to the frames pushed into `RING`
+static mut TIMESTAMPS: Option<TimestampProducer> = None;
+/// Frames offered to `RING` since the driver started
+static mut FRAMES_OFFERED: u64 = 0;
+/// Frames for the mapped outputs, set when the driver runs as a sink
+static mut OUTPUT_RING: Option<FrameRingConsumer> = None;
+static mut DRIVER_OPEN: bool = false;
 static mut BUFFER_SIZE: usize = 0;
 static mut CHANNELS: usize = 0;
+static mut OUTPUTS: usize = 0;
+static mut SAMPLE_RATE: usize = 0;
 static mut ASIO_BUFFERS: *mut ASIOBufferInfo = std::ptr::null_mut();
+/// Driver input feeding each bridge channel, 0-based
+static mut INPUT_MAP: Vec<usize> = Vec::new();
+/// Driver output fed by each bridge channel, 0-based
+static mut OUTPUT_MAP: Vec<usize> = Vec::new();
+/// Per bridge channel, resolved in start_asio so the callback never asks the driver
+static mut INPUT_ROUTES: Vec<Route> = Vec::new();
+static mut OUTPUT_ROUTES: Vec<Route> = Vec::new();
+/// The driver wants ASIOOutputReady after each switch's outputs are written
+static mut OUTPUT_READY: bool = false;
+
+/// Pending reset as `ResetReason as u8 + 1`, 0 if there is none
+static RESET: AtomicU8 = AtomicU8::new(0);
+static RESYNCS: AtomicUsize = AtomicUsize::new(0);
+static LATENCIES_CHANGED: AtomicBool = AtomicBool::new(false);
 
 // Global visualizer reference (unsafe)
 static mut VISUALIZER: Option<Arc<visualizer::AudioVisualizer>> = None;
@@ -26,124 +64,321 @@ pub fn set_visualizer(visualizer: Arc<visualizer::AudioVisualizer>) {
 
 use std::slice;
 
-enum AsioSampleType {
-    ASIOSTInt16MSB = 0,
-    ASIOSTInt24MSB = 1, // used for 20 bits as well
-    ASIOSTInt32MSB = 2,
-    ASIOSTFloat32MSB = 3, // IEEE 754 32 bit float
-    ASIOSTFloat64MSB = 4, // IEEE 754 64 bit double float
+/// How to read or write one channel's half of the double buffer
+#[derive(Clone, Copy)]
+struct ChannelFormat {
+    bytes_per_sample: usize,
+    decode: Decode,
+    encode: Encode,
+}
 
-    // these are used for 32 bit data buffer, with different alignment of the data inside
-    // 32 bit PCI bus systems can be more easily used with these
-    ASIOSTInt32MSB16 = 8,  // 32 bit data with 16 bit alignment
-    ASIOSTInt32MSB18 = 9,  // 32 bit data with 18 bit alignment
-    ASIOSTInt32MSB20 = 10, // 32 bit data with 20 bit alignment
-    ASIOSTInt32MSB24 = 11, // 32 bit data with 24 bit alignment
+/// Which driver buffer a bridge channel is read from or written to in the callback
+#[derive(Clone, Copy)]
+struct Route {
+    /// Index into `ASIO_BUFFERS`
+    buffer: usize,
+    format: ChannelFormat,
+}
 
-    ASIOSTInt16LSB = 16,
-    ASIOSTInt24LSB = 17, // used for 20 bits as well
-    ASIOSTInt32LSB = 18,
-    ASIOSTFloat32LSB = 19, // IEEE 754 32 bit float, as found on Intel x86 architecture
-    ASIOSTFloat64LSB = 20, // IEEE 754 64 bit double float, as found on Intel x86 architecture
+/// Sample type the driver uses for one of its channels
+unsafe fn channel_sample_type(channel: i32, is_input: bool) -> anyhow::Result<AsioSampleType> {
+    let mut info: ASIOChannelInfo = std::mem::zeroed();
+    info.channel = channel;
+    info.isInput = is_input as i32;
+    let rc = ASIOGetChannelInfo(&mut info);
+    anyhow::ensure!(
+        rc == AsioErrorWrapper::ASE_OK as i32,
+        "ASIOGetChannelInfo failed for channel {} ({})",
+        channel,
+        rc
+    );
+    AsioSampleType::try_from(info.type_)
+}
 
-    // these are used for 32 bit data buffer, with different alignment of the data inside
-    // 32 bit PCI bus systems can more easily used with these
-    ASIOSTInt32LSB16 = 24, // 32 bit data with 18 bit alignment
-    ASIOSTInt32LSB18 = 25, // 32 bit data with 18 bit alignment
-    ASIOSTInt32LSB20 = 26, // 32 bit data with 20 bit alignment
-    ASIOSTInt32LSB24 = 27, // 32 bit data with 24 bit alignment
+/// Converters for the given channels, failing on formats we can't convert
+unsafe fn channel_formats(
+    channels: &[usize],
+    is_input: bool,
+) -> anyhow::Result<Vec<ChannelFormat>> {
+    channels
+        .iter()
+        .map(|&channel| {
+            let ty = channel_sample_type(channel as i32, is_input)?;
+            let (Some(decode), Some(encode)) = (ty.decoder(), ty.encoder()) else {
+                anyhow::bail!(
+                    "{} channel {} uses unsupported sample type {:?}",
+                    if is_input { "input" } else { "output" },
+                    channel + 1,
+                    ty
+                );
+            };
+            Ok(ChannelFormat {
+                bytes_per_sample: ty.bytes_per_sample(),
+                decode,
+                encode,
+            })
+        })
+        .collect()
+}
+
+/// Periods of driver timestamps the reading side can fall behind by
+const TIMESTAMP_QUEUE_PERIODS: usize = 1024;
 
-    //	ASIO DSD format.
-    ASIOSTDSDInt8LSB1 = 32, // DSD 1 bit data, 8 samples per byte. First sample in Least significant bit.
-    ASIOSTDSDInt8MSB1 = 33, // DSD 1 bit data, 8 samples per byte. First sample in Most significant bit.
-    ASIOSTDSDInt8NER8 = 40, // DSD 8 bit data, 1 sample per byte. No Endianness required.
+/// `AsioTimeInfo::flags` bits for the two fields we read
+const SYSTEM_TIME_VALID: i32 = 1;
+const SAMPLE_POSITION_VALID: i32 = 1 << 1;
 
-    ASIOSTLastEntry,
+/// Sample position and system time in ns from the driver's hi/lo halves
+fn driver_time(position: ASIOSamples, system_time: ASIOTimeStamp) -> (u64, u64) {
+    (
+        ((position.hi as u64) << 32) | position.lo as u64,
+        ((system_time.hi as u64) << 32) | system_time.lo as u64,
+    )
 }
 
-impl From<i32> for AsioSampleType {
-    fn from(value: i32) -> Self {
-        unsafe { std::mem::transmute(value as i8) }
+/// ASIO 1.0 style switch, without time info
+unsafe extern "C" fn buffer_switch(double_buffer_index: i32, _direct: i32) {
+    // the SDK host sample asks for the position here as well
+    let mut time = None;
+    if TIMESTAMPS.is_some() {
+        let mut position = ASIOSamples { hi: 0, lo: 0 };
+        let mut system_time = ASIOTimeStamp { hi: 0, lo: 0 };
+        if ASIOGetSamplePosition(&mut position, &mut system_time) == AsioErrorWrapper::ASE_OK as i32
+        {
+            time = Some(driver_time(position, system_time));
+        }
     }
+    switch_buffers(double_buffer_index as usize, time);
 }
 
-unsafe extern "C" fn buffer_switch(double_buffer_index: i32, _direct: i32) {
-    let ring = RING.as_mut().unwrap();
-    let frames = BUFFER_SIZE;
-    let chans = CHANNELS;
-    let mut out = vec![0.0f32; frames * chans];
+/// Handle one half of the double buffer.
+///
+/// `time` is the driver's sample position and system time at the start of
+/// this half, if it gave us one.
+unsafe fn switch_buffers(index: usize, time: Option<(u64, u64)>) {
+    // a late callback after the buffers were disposed has nothing to work on
+    if ASIO_BUFFERS.is_null() {
+        return;
+    }
+    if let Some(ring) = RING.as_mut() {
+        if let (Some(timestamps), Some((sample_position, system_time_ns))) =
+            (TIMESTAMPS.as_mut(), time)
+        {
+            // a full queue only costs the estimator a point
+            timestamps.push(Timestamp {
+                frame: FRAMES_OFFERED,
+                sample_position,
+                system_time_ns,
+            });
+        }
+        read_inputs(ring, index);
+        FRAMES_OFFERED += BUFFER_SIZE as u64;
+    }
+    if !(*(&raw const OUTPUT_ROUTES)).is_empty() {
+        write_outputs(OUTPUT_RING.as_mut(), index);
+        if OUTPUT_READY {
+            // lets the driver send this half now instead of at the next switch
+            ASIOOutputReady();
+        }
+    }
+}
+
+/// Interleave the mapped inputs' half of the double buffer straight into the ring
+unsafe fn read_inputs(ring: &mut FrameRingProducer, double_buffer_index: usize) {
+    let routes = &*(&raw const INPUT_ROUTES);
+    // unless the ring's policy stages them, frames it has no room for are
+    // dropped before they are decoded
+    let mut chunk = ring.write_chunk(BUFFER_SIZE);
+    let frames = chunk.len();
 
-    for ch in 0..chans {
-        let buf_info = &*ASIO_BUFFERS.add(ch);
+    for (ch, route) in routes.iter().enumerate() {
+        let buf_info = &*ASIO_BUFFERS.add(route.buffer);
 
         // Get pointer to the correct double buffer
-        let buffer_ptr = buf_info.buffers[double_buffer_index as usize];
+        let buffer_ptr = buf_info.buffers[double_buffer_index];
         if buffer_ptr.is_null() {
+            for frame in chunk.frames_mut() {
+                frame[ch] = 0.0;
+            }
             continue;
         }
 
-        // Query the sample type for this channel
-        let mut info: ASIOChannelInfo = std::mem::zeroed();
-        info.channel = buf_info.channelNum;
-        info.isInput = buf_info.isInput;
-        ASIOGetChannelInfo(&mut info);
-
-        match AsioSampleType::from(info.type_) {
-            AsioSampleType::ASIOSTFloat32LSB => {
-                let slice = slice::from_raw_parts(buffer_ptr as *const f32, frames);
-                for i in 0..frames {
-                    out[i * chans + ch] = slice[i];
-                }
-            }
-            AsioSampleType::ASIOSTInt16LSB => {
-                let slice = slice::from_raw_parts(buffer_ptr as *const i16, frames);
-                for i in 0..frames {
-                    out[i * chans + ch] = slice[i] as f32 / 32768.0;
-                }
-            }
-            AsioSampleType::ASIOSTInt32LSB => {
-                let slice = slice::from_raw_parts(buffer_ptr as *const i32, frames);
-                for i in 0..frames {
-                    out[i * chans + ch] = slice[i] as f32 / 2147483648.0;
-                }
-            }
-            AsioSampleType::ASIOSTFloat64LSB => {
-                let slice = slice::from_raw_parts(buffer_ptr as *const f64, frames);
-                for i in 0..frames {
-                    out[i * chans + ch] = slice[i] as f32;
-                }
-            }
-            _ => panic!("Unsupported ASIO sample type for channel {}", ch),
+        let size = route.format.bytes_per_sample;
+        let bytes = slice::from_raw_parts(buffer_ptr as *const u8, frames * size);
+        for (frame, sample) in chunk.frames_mut().zip(bytes.chunks_exact(size)) {
+            frame[ch] = (route.format.decode)(sample);
         }
     }
 
-    // Push interleaved f32 buffer to ring
-    let amplitude = calculate_rms(&out);
-    ring.push(&out);
+    let (first, second) = chunk.as_mut_slices();
+    let amplitude = calculate_rms(first.iter().chain(second.iter()));
+    chunk.commit_all();
 
     if let Some(ref visualizer) = VISUALIZER {
         visualizer.update_amplitude(amplitude);
     }
 }
 
-fn calculate_rms(samples: &[f32]) -> f32 {
-    if samples.is_empty() {
+/// Fill the mapped outputs' half of the double buffer straight from the ring,
+/// or with silence when there is no ring yet
+unsafe fn write_outputs(mut ring: Option<&mut FrameRingConsumer>, double_buffer_index: usize) {
+    let frames = BUFFER_SIZE;
+    let routes = &*(&raw const OUTPUT_ROUTES);
+
+    // frames the ring is short of play as silence rather than whatever was
+    // left in the buffer
+    let chunk = ring.as_deref_mut().map(|ring| ring.read_chunk(frames));
+    let available = chunk.as_ref().map_or(0, |chunk| chunk.len());
+
+    for (ch, route) in routes.iter().enumerate() {
+        let buf_info = &*ASIO_BUFFERS.add(route.buffer);
+        let buffer_ptr = buf_info.buffers[double_buffer_index];
+        if buffer_ptr.is_null() {
+            continue;
+        }
+
+        let size = route.format.bytes_per_sample;
+        let bytes = slice::from_raw_parts_mut(buffer_ptr as *mut u8, frames * size);
+        let (played, silent) = bytes.split_at_mut(available * size);
+        if let Some(chunk) = &chunk {
+            for (sample, frame) in played.chunks_exact_mut(size).zip(chunk.frames()) {
+                (route.format.encode)(frame[ch], sample);
+            }
+        }
+        for sample in silent.chunks_exact_mut(size) {
+            (route.format.encode)(0.0, sample);
+        }
+    }
+
+    if let Some(chunk) = chunk {
+        chunk.commit_all();
+    }
+    if let Some(ring) = ring {
+        if available < frames {
+            ring.stats()
+                .padded_frames
+                .fetch_add((frames - available) as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+fn calculate_rms<'a>(samples: impl Iterator<Item = &'a f32>) -> f32 {
+    let (sum, count) = samples.fold((0.0f64, 0usize), |(sum, count), &x| {
+        (sum + x as f64 * x as f64, count + 1)
+    });
+    if count == 0 {
         return 0.0;
     }
 
-    let sum: f64 = samples.iter().map(|&x| x as f64 * x as f64).sum();
-    let mean = sum / samples.len() as f64;
-    mean.sqrt() as f32
+    (sum / count as f64).sqrt() as f32
+}
+
+/// Why the driver has to be closed and opened again
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResetReason {
+    /// kAsioResetRequest, usually after a change in the driver's control panel
+    Request,
+    /// kAsioBufferSizeChange
+    BufferSize,
+    /// The driver's clock moved away from the rate it was opened at
+    SampleRate,
+}
+
+impl ResetReason {
+    const ALL: [ResetReason; 3] = [
+        ResetReason::Request,
+        ResetReason::BufferSize,
+        ResetReason::SampleRate,
+    ];
+}
+
+/// What the driver reported since the last [`take_driver_events`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DriverEvents {
+    /// The driver can't go on as it is; tear the ASIO side down and open it again
+    pub reset: Option<ResetReason>,
+    /// Times the driver lost samples and its timestamps jumped
+    pub resyncs: usize,
+    /// Input or output latency changed, the buffer size did not
+    pub latencies_changed: bool,
+}
+
+/// Collect and clear what the driver reported through its callbacks.
+///
+/// The callbacks can't act on a message themselves, they may run on the
+/// driver's own threads in the middle of a buffer switch. Poll this from the
+/// thread that owns the bridge instead.
+pub fn take_driver_events() -> DriverEvents {
+    let reset = RESET.swap(0, Ordering::Relaxed);
+    DriverEvents {
+        reset: ResetReason::ALL
+            .get((reset as usize).wrapping_sub(1))
+            .copied(),
+        resyncs: RESYNCS.swap(0, Ordering::Relaxed),
+        latencies_changed: LATENCIES_CHANGED.swap(false, Ordering::Relaxed),
+    }
 }
 
-unsafe extern "C" fn sample_rate_changed(rate: ASIOSampleRate) {}
+fn request_reset(reason: ResetReason) {
+    RESET.store(reason as u8 + 1, Ordering::Relaxed);
+}
+
+unsafe extern "C" fn sample_rate_changed(rate: ASIOSampleRate) {
+    // some drivers report the rate they were just set to
+    if rate as usize != SAMPLE_RATE {
+        request_reset(ResetReason::SampleRate);
+    }
+}
+
+const SELECTOR_SUPPORTED: i32 = AsioMessageSelectors::kAsioSelectorSupported as i32;
+const ENGINE_VERSION: i32 = AsioMessageSelectors::kAsioEngineVersion as i32;
+const RESET_REQUEST: i32 = AsioMessageSelectors::kAsioResetRequest as i32;
+const BUFFER_SIZE_CHANGE: i32 = AsioMessageSelectors::kAsioBufferSizeChange as i32;
+const RESYNC_REQUEST: i32 = AsioMessageSelectors::kAsioResyncRequest as i32;
+const LATENCIES_CHANGE: i32 = AsioMessageSelectors::kAsioLatenciesChanged as i32;
+const SUPPORTS_TIME_INFO: i32 = AsioMessageSelectors::kAsioSupportsTimeInfo as i32;
+
 unsafe extern "C" fn asio_message(
     selector: i32,
     value: i32,
     msg: *mut std::ffi::c_void,
     opt: *mut f64,
 ) -> i32 {
-    0
+    match selector {
+        SELECTOR_SUPPORTED => matches!(
+            value,
+            ENGINE_VERSION
+                | RESET_REQUEST
+                | BUFFER_SIZE_CHANGE
+                | RESYNC_REQUEST
+                | LATENCIES_CHANGE
+                | SUPPORTS_TIME_INFO
+        ) as i32,
+        // ASIO 2.0 host
+        ENGINE_VERSION => 2,
+        RESET_REQUEST => {
+            request_reset(ResetReason::Request);
+            1
+        }
+        // the new size in `value`, picked up again as the preferred size on reopen
+        BUFFER_SIZE_CHANGE => {
+            if value as usize != BUFFER_SIZE {
+                request_reset(ResetReason::BufferSize);
+            }
+            1
+        }
+        RESYNC_REQUEST => {
+            RESYNCS.fetch_add(1, Ordering::Relaxed);
+            1
+        }
+        LATENCIES_CHANGE => {
+            LATENCIES_CHANGED.store(true, Ordering::Relaxed);
+            1
+        }
+        // buffer_switch_time_info is always there
+        SUPPORTS_TIME_INFO => 1,
+        _ => 0,
+    }
 }
 
 unsafe extern "C" fn buffer_switch_time_info(
@@ -151,7 +386,16 @@ unsafe extern "C" fn buffer_switch_time_info(
     index: i32,
     direct: i32,
 ) -> *mut ASIOTime {
-    buffer_switch(index, direct);
+    let mut time = None;
+    if !params.is_null() {
+        // the SDK struct is packed, asio-sys mirrors it with plain fields
+        let info = ptr::read_unaligned(&raw const (*params.cast::<AsioTime>()).time_info);
+        let valid = SYSTEM_TIME_VALID | SAMPLE_POSITION_VALID;
+        if info.flags & valid == valid {
+            time = Some(driver_time(info.sample_position, info.system_time));
+        }
+    }
+    switch_buffers(index as usize, time);
     ptr::null_mut()
 }
 
@@ -162,51 +406,210 @@ static mut CALLBACKS: ASIOCallbacks = ASIOCallbacks {
     asioMessage: Some(asio_message),
 };
 
-pub unsafe fn start_asio(ring: FrameRingProducer) -> anyhow::Result<()> {
-    RING = Some(ring);
+const MAX_DRIVERS: usize = 32;
+const MAX_NAME_LEN: usize = 32;
 
-    // Create AsioDrivers instance to enumerate drivers
-    let mut drivers = AsioDrivers::new();
+/// Rates `list_drivers` asks every driver about
+const PROBE_RATES: [usize; 8] = [
+    32_000, 44_100, 48_000, 88_200, 96_000, 176_400, 192_000, 384_000,
+];
 
-    const MAX_DRIVERS: usize = 32;
-    const MAX_NAME_LEN: usize = 32;
-    let mut name_storage = vec![[0i8; MAX_NAME_LEN]; MAX_DRIVERS];
-    let mut name_ptrs: Vec<*mut i8> = name_storage
-        .iter_mut()
-        .map(|buf| buf.as_mut_ptr())
+/// Names of the installed drivers in the form `loadDriver` takes them.
+unsafe fn driver_names(drivers: &mut AsioDrivers) -> Vec<[i8; MAX_NAME_LEN]> {
+    let mut storage = vec![[0i8; MAX_NAME_LEN]; MAX_DRIVERS];
+    let mut ptrs: Vec<*mut i8> = storage.iter_mut().map(|buf| buf.as_mut_ptr()).collect();
+    let count = drivers.getDriverNames(ptrs.as_mut_ptr(), MAX_DRIVERS as i32);
+    storage.truncate(count.clamp(0, MAX_DRIVERS as i32) as usize);
+    storage
+}
+
+/// A fixed-size, possibly unterminated C string from the driver
+fn c_name(name: &[i8]) -> String {
+    let bytes: Vec<u8> = name
+        .iter()
+        .take_while(|&&c| c != 0)
+        .map(|&c| c as u8)
         .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
 
-    // If NUX driver not found, enumerate all drivers and find one that contains 'NUX' in its name
+/// Load every installed driver in turn and report what it supports.
+///
+/// Must not be called while a driver is open for streaming; each driver is
+/// unloaded again before the next one is tried.
+pub fn list_drivers() -> Vec<AsioDriverInfo> {
+    unsafe {
+        let mut drivers = AsioDrivers::new();
+        driver_names(&mut drivers)
+            .iter_mut()
+            .enumerate()
+            .map(|(index, raw_name)| {
+                let mut info =
+                    query_driver(&mut drivers, raw_name).unwrap_or_else(|e| AsioDriverInfo {
+                        error: Some(format!("{:#}", e)),
+                        ..Default::default()
+                    });
+                info.index = index;
+                info.name = c_name(raw_name);
+                info
+            })
+            .collect()
+    }
+}
 
-    let num_drivers = drivers.getDriverNames(name_ptrs.as_mut_ptr(), MAX_DRIVERS as i32);
+unsafe fn query_driver(
+    drivers: &mut AsioDrivers,
+    name: &mut [i8; MAX_NAME_LEN],
+) -> anyhow::Result<AsioDriverInfo> {
+    anyhow::ensure!(drivers.loadDriver(name.as_mut_ptr()), "failed to load");
 
-    // Print the driver names
-    for i in 0..num_drivers {
-        let driver_ptr = name_ptrs[i as usize];
+    let mut driver_info: ASIODriverInfo = std::mem::zeroed();
+    let rc = ASIOInit(&mut driver_info);
+    let result = if rc == AsioErrorWrapper::ASE_OK as i32 {
+        let result = query_loaded_driver();
+        ASIOExit();
+        result
+    } else {
+        Err(anyhow::anyhow!("ASIOInit failed ({})", rc))
+    };
+    remove_current_driver();
+    result
+}
 
-        if !driver_ptr.is_null() {
-            let driver_cstr = std::ffi::CStr::from_ptr(driver_ptr);
-            if let Ok(driver_str) = driver_cstr.to_str() {
-                // Check if this is the NUX driver (case-insensitive search)
-                if driver_str.contains("NUX") && drivers.loadDriver(driver_ptr) {
-                    println!("Driver {}: {} Loaded", i, driver_str);
-                    break;
-                }
+/// Names the driver gives its first `count` input or output channels
+unsafe fn channel_names(count: i32, is_input: bool) -> Vec<String> {
+    (0..count)
+        .map(|channel| {
+            let mut info: ASIOChannelInfo = std::mem::zeroed();
+            info.channel = channel;
+            info.isInput = is_input as i32;
+            if ASIOGetChannelInfo(&mut info) == AsioErrorWrapper::ASE_OK as i32 {
+                c_name(&info.name)
+            } else {
+                String::new()
             }
+        })
+        .collect()
+}
+
+unsafe fn query_loaded_driver() -> anyhow::Result<AsioDriverInfo> {
+    let mut ins = 0;
+    let mut outs = 0;
+    let rc = ASIOGetChannels(&mut ins, &mut outs);
+    anyhow::ensure!(
+        rc == AsioErrorWrapper::ASE_OK as i32,
+        "ASIOGetChannels failed ({})",
+        rc
+    );
+
+    let mut min = 0;
+    let mut max = 0;
+    let mut pref = 0;
+    let mut gran = 0;
+    let rc = ASIOGetBufferSize(&mut min, &mut max, &mut pref, &mut gran);
+    anyhow::ensure!(
+        rc == AsioErrorWrapper::ASE_OK as i32,
+        "ASIOGetBufferSize failed ({})",
+        rc
+    );
+
+    let mut rate = 0.0;
+    get_sample_rate(&mut rate);
+
+    Ok(AsioDriverInfo {
+        inputs: channel_names(ins, true),
+        outputs: channel_names(outs, false),
+        buffer_size: BufferSizeRange {
+            min: min as usize,
+            max: max as usize,
+            preferred: pref as usize,
+            granularity: gran as i64,
+        },
+        sample_rate: rate as usize,
+        supported_rates: PROBE_RATES
+            .into_iter()
+            .filter(|&rate| can_sample_rate(rate as f64) == AsioErrorWrapper::ASE_OK as i32)
+            .collect(),
+        ..Default::default()
+    })
+}
+
+/// A loaded and initialized driver; dropping it exits and unloads the driver.
+///
+/// Only one can exist at a time, the driver state is global.
+struct AsioDriver;
+
+impl Drop for AsioDriver {
+    fn drop(&mut self) {
+        unsafe {
+            ASIOExit();
+            remove_current_driver();
+
+            // back to the state open_asio expects
+            RING = None;
+            TIMESTAMPS = None;
+            OUTPUT_RING = None;
+            OUTPUT_READY = false;
+            CHANNELS = 0;
+            OUTPUTS = 0;
+            INPUT_MAP = Vec::new();
+            OUTPUT_MAP = Vec::new();
+            INPUT_ROUTES = Vec::new();
+            OUTPUT_ROUTES = Vec::new();
+            DRIVER_OPEN = false;
         }
+        println!("ASIO driver closed");
     }
+}
+
+/// Load the driver picked by `driver` and query its buffer layout.
+///
+/// If `sample_rate` is given the driver is switched to it first, otherwise it
+/// keeps whatever rate it is currently clocked at.
+unsafe fn open_asio(
+    driver: Option<&DriverSelector>,
+    sample_rate: Option<usize>,
+) -> anyhow::Result<AsioDriver> {
+    anyhow::ensure!(!DRIVER_OPEN, "an ASIO driver is already open");
+    // anything still pending is about a driver that's gone
+    take_driver_events();
+
+    // Create AsioDrivers instance to enumerate drivers
+    let mut drivers = AsioDrivers::new();
+    let mut raw_names = driver_names(&mut drivers);
+    let names: Vec<String> = raw_names.iter().map(|name| c_name(name)).collect();
+
+    // 1. load
+    let index = DriverSelector::select(driver, &names)?;
+    anyhow::ensure!(
+        drivers.loadDriver(raw_names[index].as_mut_ptr()),
+        "failed to load ASIO driver {}",
+        names[index]
+    );
+    println!("Driver {}: {} Loaded", index, names[index]);
+    DRIVER_OPEN = true;
+    // unloads the driver again if anything below fails
+    let guard = AsioDriver;
 
     // 2. init
     let mut info: ASIODriverInfo = unsafe { std::mem::zeroed() };
     let rc = ASIOInit(&mut info);
-    assert_eq!(rc, AsioErrorWrapper::ASE_OK as i32);
+    anyhow::ensure!(
+        rc == AsioErrorWrapper::ASE_OK as i32,
+        "ASIOInit failed for {} ({})",
+        names[index],
+        rc
+    );
 
     // 3. channels
     let mut ins = 0;
     let mut outs = 0;
     let rc = ASIOGetChannels(&mut ins, &mut outs);
-    assert_eq!(rc, AsioErrorWrapper::ASE_OK as i32);
-    CHANNELS = ins as usize;
+    anyhow::ensure!(
+        rc == AsioErrorWrapper::ASE_OK as i32,
+        "ASIOGetChannels failed ({})",
+        rc
+    );
 
     // 4. buffer size
     let mut min = 0;
@@ -214,53 +617,463 @@ pub unsafe fn start_asio(ring: FrameRingProducer) -> anyhow::Result<()> {
     let mut pref = 0;
     let mut gran = 0;
     let rc = ASIOGetBufferSize(&mut min, &mut max, &mut pref, &mut gran);
-    assert_eq!(rc, AsioErrorWrapper::ASE_OK as i32);
+    anyhow::ensure!(
+        rc == AsioErrorWrapper::ASE_OK as i32,
+        "ASIOGetBufferSize failed ({})",
+        rc
+    );
 
     BUFFER_SIZE = pref as usize;
 
+    // 5. sample rate
+    if let Some(rate) = sample_rate {
+        let rc = can_sample_rate(rate as f64);
+        anyhow::ensure!(
+            rc == AsioErrorWrapper::ASE_OK as i32,
+            "ASIO driver can't run at {} Hz",
+            rate
+        );
+        let rc = set_sample_rate(rate as f64);
+        anyhow::ensure!(
+            rc == AsioErrorWrapper::ASE_OK as i32,
+            "ASIO driver refused {} Hz: {}",
+            rate,
+            rc
+        );
+    }
+    let mut rate = 0.0;
+    let rc = get_sample_rate(&mut rate);
+    anyhow::ensure!(
+        rc == AsioErrorWrapper::ASE_OK as i32 && rate > 0.0,
+        "ASIO driver has no sample rate: {}",
+        rc
+    );
+    SAMPLE_RATE = rate as usize;
+
     println!(
         "ASIO driver info: {:?}",
         std::ffi::CStr::from_ptr(&info.errorMessage as *const i8)
     );
     println!("Channels: ins={}, outs={}", ins, outs);
+    println!("Sample rate: {}", SAMPLE_RATE);
     println!(
         "Buffer size: min={}, max={}, pref={}, gran={}",
         min, max, pref, gran
     );
 
+    Ok(guard)
+}
+
+/// Resolve which driver channels carry the bridge channels, by default the first two.
+///
+/// Fails on formats the buffer switch can't convert now, not once streaming.
+unsafe fn map_channels(map: Option<&ChannelMap>, is_input: bool) -> anyhow::Result<Vec<usize>> {
+    let mut ins = 0;
+    let mut outs = 0;
+    let rc = ASIOGetChannels(&mut ins, &mut outs);
+    anyhow::ensure!(
+        rc == AsioErrorWrapper::ASE_OK as i32,
+        "ASIOGetChannels failed ({})",
+        rc
+    );
+    let (count, direction) = if is_input {
+        (ins, "input")
+    } else {
+        (outs, "output")
+    };
+
+    let names = channel_names(count, is_input);
+    let channels = match map {
+        Some(map) => map.resolve(&names)?,
+        None => ChannelMap::first(2, names.len()).resolve(&names)?,
+    };
+    channel_formats(&channels, is_input)?;
+
+    for (ch, &channel) in channels.iter().enumerate() {
+        println!(
+            "Bridge channel {} {} {} {} ({})",
+            ch + 1,
+            if is_input { "<-" } else { "->" },
+            direction,
+            channel + 1,
+            names[channel]
+        );
+    }
+    Ok(channels)
+}
+
+/// A started driver, returned by `start_asio`.
+///
+/// `stop`, or dropping the handle, stops the driver, disposes its buffers and
+/// then exits and unloads it.
+struct AsioHandle {
+    running: bool,
+    /// The driver fills in the buffer pointers, `ASIO_BUFFERS` points here
+    buffers: Vec<ASIOBufferInfo>,
+    _driver: AsioDriver,
+}
+
+impl AsioHandle {
+    fn stop(mut self) -> anyhow::Result<()> {
+        let rc = unsafe { ASIOStop() };
+        // a driver that didn't stop may still call back, so drop tries again
+        anyhow::ensure!(
+            rc == AsioErrorWrapper::ASE_OK as i32,
+            "ASIOStop failed: {}",
+            rc
+        );
+        self.running = false;
+        Ok(())
+    }
+}
+
+impl Drop for AsioHandle {
+    fn drop(&mut self) {
+        unsafe {
+            if self.running {
+                ASIOStop();
+            }
+            // no more callbacks, the buffer infos can go with the handle
+            ASIODisposeBuffers();
+            ASIO_BUFFERS = ptr::null_mut();
+        }
+    }
+}
+
+/// Create buffers for the mapped channels and start the driver.
+///
+/// The source and sink rings must be in place, the callback starts right away.
+/// On failure the driver is unloaded.
+unsafe fn start_asio(driver: AsioDriver) -> anyhow::Result<AsioHandle> {
+    // one buffer per mapped input, even if it feeds several bridge channels
+    let input_map = &*(&raw const INPUT_MAP);
+    let mut inputs = input_map.clone();
+    inputs.sort_unstable();
+    inputs.dedup();
+
+    let output_map = &*(&raw const OUTPUT_MAP);
+
+    // everything the callback needs, before the driver can call it
+    let formats = channel_formats(input_map, true)?;
+    INPUT_ROUTES = input_map
+        .iter()
+        .zip(formats)
+        .map(|(input, format)| Route {
+            buffer: inputs.binary_search(input).unwrap(),
+            format,
+        })
+        .collect();
+    let formats = channel_formats(output_map, false)?;
+    OUTPUT_ROUTES = formats
+        .into_iter()
+        .enumerate()
+        .map(|(ch, format)| Route {
+            buffer: inputs.len() + ch,
+            format,
+        })
+        .collect();
+
     // Prepare input buffers
     let mut buffers = Vec::new();
-    for i in 0..ins {
+    for &input in &inputs {
         buffers.push(ASIOBufferInfo {
-            isInput: 1,    // 1 = input
-            channelNum: i, // unique index
+            isInput: 1,               // 1 = input
+            channelNum: input as i32, // unique index
             buffers: [ptr::null_mut(), ptr::null_mut()],
         });
     }
 
     // Prepare output buffers
-    for i in 0..outs {
+    for &output in output_map {
         buffers.push(ASIOBufferInfo {
-            isInput: 0,    // 0 = output
-            channelNum: i, // unique index
+            isInput: 0,                // 0 = output
+            channelNum: output as i32, // unique index
             buffers: [ptr::null_mut(), ptr::null_mut()],
         });
     }
 
-    ASIO_BUFFERS = buffers.leak().as_mut_ptr();
-
-    let rc = unsafe {
-        ASIOCreateBuffers(
-            ASIO_BUFFERS,
-            ins + outs, // total number of buffers, inputs + outputs
-            BUFFER_SIZE as i32,
-            &raw mut CALLBACKS,
-        )
+    let rc = ASIOCreateBuffers(
+        buffers.as_mut_ptr(),
+        buffers.len() as i32, // total number of buffers, inputs + outputs
+        BUFFER_SIZE as i32,
+        &raw mut CALLBACKS,
+    );
+    anyhow::ensure!(
+        rc == AsioErrorWrapper::ASE_OK as i32,
+        "ASIOCreateBuffers failed ({})",
+        rc
+    );
+    // disposes the buffers again if starting fails
+    let mut handle = AsioHandle {
+        running: false,
+        buffers,
+        _driver: driver,
     };
+    ASIO_BUFFERS = handle.buffers.as_mut_ptr();
+
+    // the driver may play both halves before the first switch is handled
+    for route in &*(&raw const OUTPUT_ROUTES) {
+        let buf_info = &*ASIO_BUFFERS.add(route.buffer);
+        for &buffer_ptr in &buf_info.buffers {
+            if !buffer_ptr.is_null() {
+                // all-zero bytes are silence in every integer and float type
+                ptr::write_bytes(
+                    buffer_ptr as *mut u8,
+                    0,
+                    BUFFER_SIZE * route.format.bytes_per_sample,
+                );
+            }
+        }
+    }
+    // ASE_OK means supported, drivers without it answer ASE_NotPresent
+    OUTPUT_READY = !output_map.is_empty() && ASIOOutputReady() == AsioErrorWrapper::ASE_OK as i32;
+    if OUTPUT_READY {
+        println!("Driver supports ASIOOutputReady");
+    }
 
-    assert_eq!(rc, AsioErrorWrapper::ASE_OK as i32);
     let rc = ASIOStart();
-    assert_eq!(rc, AsioErrorWrapper::ASE_OK as i32);
+    anyhow::ensure!(
+        rc == AsioErrorWrapper::ASE_OK as i32,
+        "ASIOStart failed ({})",
+        rc
+    );
+    handle.running = true;
+
+    Ok(handle)
+}
 
+/// Open the driver and map the bridge channels onto its inputs.
+unsafe fn open_source(
+    driver: Option<&DriverSelector>,
+    inputs: Option<&ChannelMap>,
+    sample_rate: Option<usize>,
+) -> anyhow::Result<AsioDriver> {
+    let guard = open_asio(driver, sample_rate)?;
+    let channels = map_channels(inputs, true)?;
+    CHANNELS = channels.len();
+    INPUT_MAP = channels;
+    Ok(guard)
+}
+
+/// Open the driver and map the bridge channels onto its outputs.
+unsafe fn open_sink(
+    driver: Option<&DriverSelector>,
+    outputs: Option<&ChannelMap>,
+    sample_rate: Option<usize>,
+) -> anyhow::Result<AsioDriver> {
+    let guard = open_asio(driver, sample_rate)?;
+    let channels = map_channels(outputs, false)?;
+    let mut unique = channels.clone();
+    unique.sort_unstable();
+    unique.dedup();
+    anyhow::ensure!(
+        unique.len() == channels.len(),
+        "each ASIO output can only be fed by one bridge channel"
+    );
+    OUTPUTS = channels.len();
+    OUTPUT_MAP = channels;
+    Ok(guard)
+}
+
+/// Make sure a driver opened again still runs the way it was first reported.
+unsafe fn check_reopened(sample_rate: usize, block_size: usize) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        SAMPLE_RATE == sample_rate && BUFFER_SIZE == block_size,
+        "ASIO driver now runs at {} Hz with {} frame buffers instead of {} Hz with {}",
+        SAMPLE_RATE,
+        BUFFER_SIZE,
+        sample_rate,
+        block_size
+    );
     Ok(())
 }
+
+/// Opens the driver and maps the bridge channels, `open_source` or `open_sink`
+type OpenDriver = unsafe fn(
+    Option<&DriverSelector>,
+    Option<&ChannelMap>,
+    Option<usize>,
+) -> anyhow::Result<AsioDriver>;
+
+/// The driver behind an [`AsioSource`] or [`AsioSink`], with what it takes to
+/// load it again after a stop unloaded it.
+struct Driver {
+    open: OpenDriver,
+    selector: Option<DriverSelector>,
+    channels: Option<ChannelMap>,
+    requested_rate: Option<usize>,
+    sample_rate: usize,
+    block_size: usize,
+    /// Loaded but not started
+    driver: Option<AsioDriver>,
+    handle: Option<AsioHandle>,
+}
+
+impl Driver {
+    unsafe fn open(
+        open: OpenDriver,
+        selector: Option<&DriverSelector>,
+        channels: Option<&ChannelMap>,
+        sample_rate: Option<usize>,
+    ) -> anyhow::Result<Self> {
+        let driver = open(selector, channels, sample_rate)?;
+        Ok(Self {
+            open,
+            selector: selector.cloned(),
+            channels: channels.cloned(),
+            requested_rate: sample_rate,
+            sample_rate: SAMPLE_RATE,
+            block_size: BUFFER_SIZE,
+            driver: Some(driver),
+            handle: None,
+        })
+    }
+
+    /// Load the driver again if a stop unloaded it, let `install` put the
+    /// callback's state in place and start it; does nothing if it's running
+    unsafe fn start(&mut self, install: impl FnOnce()) -> anyhow::Result<()> {
+        if self.handle.is_some() {
+            return Ok(());
+        }
+        let driver = match self.driver.take() {
+            Some(driver) => driver,
+            None => {
+                let driver = (self.open)(
+                    self.selector.as_ref(),
+                    self.channels.as_ref(),
+                    self.requested_rate,
+                )?;
+                check_reopened(self.sample_rate, self.block_size)?;
+                driver
+            }
+        };
+        install();
+        self.handle = Some(start_asio(driver)?);
+        Ok(())
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        match self.handle.take() {
+            Some(handle) => handle.stop(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// ASIO driver input, pushing the mapped input channels into the ring.
+///
+/// The driver stays loaded from `new` until `stop`; starting again loads it
+/// again with the same settings.
+pub struct AsioSource {
+    driver: Driver,
+    channels: usize,
+    /// Handed to the callback on the next start
+    timestamps: Option<TimestampProducer>,
+}
+
+impl AsioSource {
+    /// Open the driver picked by `driver`, switching it to `sample_rate` if given.
+    ///
+    /// Without a selector the only installed driver is used. `inputs` picks the
+    /// driver inputs feeding each bridge channel, by default the first two.
+    pub fn new(
+        driver: Option<&DriverSelector>,
+        inputs: Option<&ChannelMap>,
+        sample_rate: Option<usize>,
+    ) -> anyhow::Result<Self> {
+        unsafe {
+            let driver = Driver::open(open_source, driver, inputs, sample_rate)?;
+            Ok(Self {
+                driver,
+                channels: CHANNELS,
+                timestamps: None,
+            })
+        }
+    }
+}
+
+impl AudioSource for AsioSource {
+    fn sample_rate(&self) -> usize {
+        self.driver.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn block_size(&self) -> usize {
+        self.driver.block_size
+    }
+
+    fn start(&mut self, ring: FrameRingProducer) -> anyhow::Result<()> {
+        let timestamps = &mut self.timestamps;
+        unsafe {
+            self.driver.start(|| {
+                RING = Some(ring);
+                TIMESTAMPS = timestamps.take();
+                FRAMES_OFFERED = 0;
+            })
+        }
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        self.driver.stop()
+    }
+
+    fn timestamps(&mut self) -> Option<TimestampConsumer> {
+        let (producer, consumer) = new_timestamp_queue(TIMESTAMP_QUEUE_PERIODS);
+        self.timestamps = Some(producer);
+        Some(consumer)
+    }
+}
+
+/// ASIO driver output, playing frames from the ring on the mapped output channels.
+///
+/// The driver stays loaded from `new` until `stop`; starting again loads it
+/// again with the same settings.
+pub struct AsioSink {
+    driver: Driver,
+    channels: usize,
+}
+
+impl AsioSink {
+    /// Open the driver picked by `driver`, switching it to `sample_rate` if given.
+    ///
+    /// Without a selector the only installed driver is used. `outputs` picks the
+    /// driver output each bridge channel plays on, by default the first two.
+    pub fn new(
+        driver: Option<&DriverSelector>,
+        outputs: Option<&ChannelMap>,
+        sample_rate: Option<usize>,
+    ) -> anyhow::Result<Self> {
+        unsafe {
+            let driver = Driver::open(open_sink, driver, outputs, sample_rate)?;
+            Ok(Self {
+                driver,
+                channels: OUTPUTS,
+            })
+        }
+    }
+}
+
+impl AudioSink for AsioSink {
+    fn sample_rate(&self) -> usize {
+        self.driver.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn block_size(&self) -> usize {
+        self.driver.block_size
+    }
+
+    fn start(&mut self, ring: FrameRingConsumer) -> anyhow::Result<()> {
+        unsafe { self.driver.start(|| OUTPUT_RING = Some(ring)) }
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        self.driver.stop()
+    }
+}
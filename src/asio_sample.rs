@@ -0,0 +1,415 @@
+//! ASIO sample formats and their conversion to and from `f32`.
+//!
+//! Kept apart from the driver code so it builds, and is tested, everywhere.
+
+/// Sample layout of an ASIO channel buffer, `ASIOChannelInfo::type_`.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AsioSampleType {
+    ASIOSTInt16MSB = 0,
+    ASIOSTInt24MSB = 1, // used for 20 bits as well
+    ASIOSTInt32MSB = 2,
+    ASIOSTFloat32MSB = 3, // IEEE 754 32 bit float
+    ASIOSTFloat64MSB = 4, // IEEE 754 64 bit double float
+
+    // these are used for 32 bit data buffer, with different alignment of the data inside
+    // 32 bit PCI bus systems can be more easily used with these
+    ASIOSTInt32MSB16 = 8,  // 32 bit data with 16 bit alignment
+    ASIOSTInt32MSB18 = 9,  // 32 bit data with 18 bit alignment
+    ASIOSTInt32MSB20 = 10, // 32 bit data with 20 bit alignment
+    ASIOSTInt32MSB24 = 11, // 32 bit data with 24 bit alignment
+
+    ASIOSTInt16LSB = 16,
+    ASIOSTInt24LSB = 17, // used for 20 bits as well
+    ASIOSTInt32LSB = 18,
+    ASIOSTFloat32LSB = 19, // IEEE 754 32 bit float, as found on Intel x86 architecture
+    ASIOSTFloat64LSB = 20, // IEEE 754 64 bit double float, as found on Intel x86 architecture
+
+    // these are used for 32 bit data buffer, with different alignment of the data inside
+    // 32 bit PCI bus systems can more easily used with these
+    ASIOSTInt32LSB16 = 24, // 32 bit data with 16 bit alignment
+    ASIOSTInt32LSB18 = 25, // 32 bit data with 18 bit alignment
+    ASIOSTInt32LSB20 = 26, // 32 bit data with 20 bit alignment
+    ASIOSTInt32LSB24 = 27, // 32 bit data with 24 bit alignment
+
+    //	ASIO DSD format.
+    ASIOSTDSDInt8LSB1 = 32, // DSD 1 bit data, 8 samples per byte. First sample in Least significant bit.
+    ASIOSTDSDInt8MSB1 = 33, // DSD 1 bit data, 8 samples per byte. First sample in Most significant bit.
+    ASIOSTDSDInt8NER8 = 40, // DSD 8 bit data, 1 sample per byte. No Endianness required.
+}
+
+impl TryFrom<i32> for AsioSampleType {
+    type Error = anyhow::Error;
+
+    fn try_from(value: i32) -> anyhow::Result<Self> {
+        use AsioSampleType::*;
+        Ok(match value {
+            0 => ASIOSTInt16MSB,
+            1 => ASIOSTInt24MSB,
+            2 => ASIOSTInt32MSB,
+            3 => ASIOSTFloat32MSB,
+            4 => ASIOSTFloat64MSB,
+            8 => ASIOSTInt32MSB16,
+            9 => ASIOSTInt32MSB18,
+            10 => ASIOSTInt32MSB20,
+            11 => ASIOSTInt32MSB24,
+            16 => ASIOSTInt16LSB,
+            17 => ASIOSTInt24LSB,
+            18 => ASIOSTInt32LSB,
+            19 => ASIOSTFloat32LSB,
+            20 => ASIOSTFloat64LSB,
+            24 => ASIOSTInt32LSB16,
+            25 => ASIOSTInt32LSB18,
+            26 => ASIOSTInt32LSB20,
+            27 => ASIOSTInt32LSB24,
+            32 => ASIOSTDSDInt8LSB1,
+            33 => ASIOSTDSDInt8MSB1,
+            40 => ASIOSTDSDInt8NER8,
+            _ => anyhow::bail!("unknown ASIO sample type {}", value),
+        })
+    }
+}
+
+/// Converts one sample, given exactly [`AsioSampleType::bytes_per_sample`] bytes
+pub type Decode = fn(&[u8]) -> f32;
+
+/// Writes one sample into exactly [`AsioSampleType::bytes_per_sample`] bytes
+pub type Encode = fn(f32, &mut [u8]);
+
+impl AsioSampleType {
+    /// Size of one sample in the channel buffer
+    pub fn bytes_per_sample(self) -> usize {
+        use AsioSampleType::*;
+        match self {
+            ASIOSTInt16MSB | ASIOSTInt16LSB => 2,
+            ASIOSTInt24MSB | ASIOSTInt24LSB => 3,
+            ASIOSTFloat64MSB | ASIOSTFloat64LSB => 8,
+            ASIOSTDSDInt8LSB1 | ASIOSTDSDInt8MSB1 | ASIOSTDSDInt8NER8 => 1,
+            _ => 4,
+        }
+    }
+
+    /// The conversion to `f32`, or `None` for the DSD formats.
+    pub fn decoder(self) -> Option<Decode> {
+        use AsioSampleType::*;
+        Some(match self {
+            ASIOSTInt16MSB => decode_int16_msb,
+            ASIOSTInt24MSB => decode_int24_msb,
+            ASIOSTInt32MSB => decode_int32_msb,
+            ASIOSTFloat32MSB => decode_float32_msb,
+            ASIOSTFloat64MSB => decode_float64_msb,
+            ASIOSTInt32MSB16 => decode_int32_msb_aligned::<16>,
+            ASIOSTInt32MSB18 => decode_int32_msb_aligned::<18>,
+            ASIOSTInt32MSB20 => decode_int32_msb_aligned::<20>,
+            ASIOSTInt32MSB24 => decode_int32_msb_aligned::<24>,
+            ASIOSTInt16LSB => decode_int16_lsb,
+            ASIOSTInt24LSB => decode_int24_lsb,
+            ASIOSTInt32LSB => decode_int32_lsb,
+            ASIOSTFloat32LSB => decode_float32_lsb,
+            ASIOSTFloat64LSB => decode_float64_lsb,
+            ASIOSTInt32LSB16 => decode_int32_lsb_aligned::<16>,
+            ASIOSTInt32LSB18 => decode_int32_lsb_aligned::<18>,
+            ASIOSTInt32LSB20 => decode_int32_lsb_aligned::<20>,
+            ASIOSTInt32LSB24 => decode_int32_lsb_aligned::<24>,
+            ASIOSTDSDInt8LSB1 | ASIOSTDSDInt8MSB1 | ASIOSTDSDInt8NER8 => return None,
+        })
+    }
+
+    /// The conversion from `f32`, or `None` for the DSD formats.
+    pub fn encoder(self) -> Option<Encode> {
+        use AsioSampleType::*;
+        Some(match self {
+            ASIOSTInt16MSB => encode_int16_msb,
+            ASIOSTInt24MSB => encode_int24_msb,
+            ASIOSTInt32MSB => encode_int32_msb,
+            ASIOSTFloat32MSB => encode_float32_msb,
+            ASIOSTFloat64MSB => encode_float64_msb,
+            ASIOSTInt32MSB16 => encode_int32_msb_aligned::<16>,
+            ASIOSTInt32MSB18 => encode_int32_msb_aligned::<18>,
+            ASIOSTInt32MSB20 => encode_int32_msb_aligned::<20>,
+            ASIOSTInt32MSB24 => encode_int32_msb_aligned::<24>,
+            ASIOSTInt16LSB => encode_int16_lsb,
+            ASIOSTInt24LSB => encode_int24_lsb,
+            ASIOSTInt32LSB => encode_int32_lsb,
+            ASIOSTFloat32LSB => encode_float32_lsb,
+            ASIOSTFloat64LSB => encode_float64_lsb,
+            ASIOSTInt32LSB16 => encode_int32_lsb_aligned::<16>,
+            ASIOSTInt32LSB18 => encode_int32_lsb_aligned::<18>,
+            ASIOSTInt32LSB20 => encode_int32_lsb_aligned::<20>,
+            ASIOSTInt32LSB24 => encode_int32_lsb_aligned::<24>,
+            ASIOSTDSDInt8LSB1 | ASIOSTDSDInt8MSB1 | ASIOSTDSDInt8NER8 => return None,
+        })
+    }
+}
+
+/// Full scale of a signed integer with `bits` bits
+fn full_scale(bits: u32) -> f32 {
+    (1u64 << (bits - 1)) as f32
+}
+
+pub fn decode_int16_lsb(bytes: &[u8]) -> f32 {
+    i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / full_scale(16)
+}
+
+pub fn decode_int16_msb(bytes: &[u8]) -> f32 {
+    i16::from_be_bytes([bytes[0], bytes[1]]) as f32 / full_scale(16)
+}
+
+/// Packed 3-byte samples
+pub fn decode_int24_lsb(bytes: &[u8]) -> f32 {
+    // place in the top of an i32 and shift back down to sign-extend
+    (i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8) as f32 / full_scale(24)
+}
+
+/// Packed 3-byte samples
+pub fn decode_int24_msb(bytes: &[u8]) -> f32 {
+    (i32::from_be_bytes([bytes[0], bytes[1], bytes[2], 0]) >> 8) as f32 / full_scale(24)
+}
+
+pub fn decode_int32_lsb(bytes: &[u8]) -> f32 {
+    i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32 / full_scale(32)
+}
+
+pub fn decode_int32_msb(bytes: &[u8]) -> f32 {
+    i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32 / full_scale(32)
+}
+
+/// `BITS` bit samples right-aligned in a 32-bit container
+pub fn decode_int32_lsb_aligned<const BITS: u32>(bytes: &[u8]) -> f32 {
+    let raw = i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    // drop whatever is above the sample and sign-extend from its top bit
+    ((raw << (32 - BITS)) >> (32 - BITS)) as f32 / full_scale(BITS)
+}
+
+/// `BITS` bit samples right-aligned in a 32-bit container
+pub fn decode_int32_msb_aligned<const BITS: u32>(bytes: &[u8]) -> f32 {
+    let raw = i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    ((raw << (32 - BITS)) >> (32 - BITS)) as f32 / full_scale(BITS)
+}
+
+pub fn decode_float32_lsb(bytes: &[u8]) -> f32 {
+    f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+pub fn decode_float32_msb(bytes: &[u8]) -> f32 {
+    f32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+pub fn decode_float64_lsb(bytes: &[u8]) -> f32 {
+    let mut raw = [0u8; 8];
+    raw.copy_from_slice(&bytes[..8]);
+    f64::from_le_bytes(raw) as f32
+}
+
+pub fn decode_float64_msb(bytes: &[u8]) -> f32 {
+    let mut raw = [0u8; 8];
+    raw.copy_from_slice(&bytes[..8]);
+    f64::from_be_bytes(raw) as f32
+}
+
+/// Scale to a signed integer with `bits` bits, clipping anything outside [-1, 1)
+fn to_int(sample: f32, bits: u32) -> i32 {
+    let scale = full_scale(bits) as f64;
+    (sample as f64 * scale).round().clamp(-scale, scale - 1.0) as i32
+}
+
+pub fn encode_int16_lsb(sample: f32, out: &mut [u8]) {
+    out[..2].copy_from_slice(&(to_int(sample, 16) as i16).to_le_bytes());
+}
+
+pub fn encode_int16_msb(sample: f32, out: &mut [u8]) {
+    out[..2].copy_from_slice(&(to_int(sample, 16) as i16).to_be_bytes());
+}
+
+/// Packed 3-byte samples
+pub fn encode_int24_lsb(sample: f32, out: &mut [u8]) {
+    out[..3].copy_from_slice(&to_int(sample, 24).to_le_bytes()[..3]);
+}
+
+/// Packed 3-byte samples
+pub fn encode_int24_msb(sample: f32, out: &mut [u8]) {
+    out[..3].copy_from_slice(&to_int(sample, 24).to_be_bytes()[1..]);
+}
+
+pub fn encode_int32_lsb(sample: f32, out: &mut [u8]) {
+    out[..4].copy_from_slice(&to_int(sample, 32).to_le_bytes());
+}
+
+pub fn encode_int32_msb(sample: f32, out: &mut [u8]) {
+    out[..4].copy_from_slice(&to_int(sample, 32).to_be_bytes());
+}
+
+/// `BITS` bit samples right-aligned and sign-extended in a 32-bit container
+pub fn encode_int32_lsb_aligned<const BITS: u32>(sample: f32, out: &mut [u8]) {
+    out[..4].copy_from_slice(&to_int(sample, BITS).to_le_bytes());
+}
+
+/// `BITS` bit samples right-aligned and sign-extended in a 32-bit container
+pub fn encode_int32_msb_aligned<const BITS: u32>(sample: f32, out: &mut [u8]) {
+    out[..4].copy_from_slice(&to_int(sample, BITS).to_be_bytes());
+}
+
+pub fn encode_float32_lsb(sample: f32, out: &mut [u8]) {
+    out[..4].copy_from_slice(&sample.to_le_bytes());
+}
+
+pub fn encode_float32_msb(sample: f32, out: &mut [u8]) {
+    out[..4].copy_from_slice(&sample.to_be_bytes());
+}
+
+pub fn encode_float64_lsb(sample: f32, out: &mut [u8]) {
+    out[..8].copy_from_slice(&(sample as f64).to_le_bytes());
+}
+
+pub fn encode_float64_msb(sample: f32, out: &mut [u8]) {
+    out[..8].copy_from_slice(&(sample as f64).to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use AsioSampleType::*;
+
+    /// Decode `bytes` as `ty`, checking the length matches the format
+    fn decode(ty: AsioSampleType, bytes: &[u8]) -> f32 {
+        assert_eq!(bytes.len(), ty.bytes_per_sample(), "{:?}", ty);
+        ty.decoder().unwrap()(bytes)
+    }
+
+    #[test]
+    fn int16() {
+        assert_eq!(decode(ASIOSTInt16LSB, &[0x00, 0x40]), 0.5);
+        assert_eq!(decode(ASIOSTInt16LSB, &[0x00, 0x80]), -1.0);
+        assert_eq!(decode(ASIOSTInt16LSB, &[0xff, 0xff]), -1.0 / 32768.0);
+        assert_eq!(decode(ASIOSTInt16MSB, &[0x40, 0x00]), 0.5);
+        assert_eq!(decode(ASIOSTInt16MSB, &[0x80, 0x00]), -1.0);
+        assert_eq!(decode(ASIOSTInt16MSB, &[0x7f, 0xff]), 32767.0 / 32768.0);
+    }
+
+    #[test]
+    fn int24_packed() {
+        assert_eq!(decode(ASIOSTInt24LSB, &[0x00, 0x00, 0x40]), 0.5);
+        assert_eq!(decode(ASIOSTInt24LSB, &[0x00, 0x00, 0x80]), -1.0);
+        assert_eq!(
+            decode(ASIOSTInt24LSB, &[0x01, 0x00, 0x00]),
+            1.0 / 8_388_608.0
+        );
+        assert_eq!(
+            decode(ASIOSTInt24LSB, &[0xff, 0xff, 0xff]),
+            -1.0 / 8_388_608.0
+        );
+        assert_eq!(decode(ASIOSTInt24MSB, &[0x40, 0x00, 0x00]), 0.5);
+        assert_eq!(decode(ASIOSTInt24MSB, &[0xc0, 0x00, 0x00]), -0.5);
+        assert_eq!(
+            decode(ASIOSTInt24MSB, &[0x00, 0x00, 0x01]),
+            1.0 / 8_388_608.0
+        );
+    }
+
+    #[test]
+    fn int32() {
+        assert_eq!(decode(ASIOSTInt32LSB, &[0, 0, 0, 0x40]), 0.5);
+        assert_eq!(decode(ASIOSTInt32LSB, &[0, 0, 0, 0x80]), -1.0);
+        assert_eq!(decode(ASIOSTInt32LSB, &[0, 0, 0, 0xe0]), -0.25);
+        assert_eq!(decode(ASIOSTInt32MSB, &[0x40, 0, 0, 0]), 0.5);
+        assert_eq!(decode(ASIOSTInt32MSB, &[0x80, 0, 0, 0]), -1.0);
+    }
+
+    #[test]
+    fn int32_aligned() {
+        // half scale is the bit below the sample's sign bit
+        assert_eq!(decode(ASIOSTInt32LSB16, &0x4000i32.to_le_bytes()), 0.5);
+        assert_eq!(decode(ASIOSTInt32LSB18, &0x1_0000i32.to_le_bytes()), 0.5);
+        assert_eq!(decode(ASIOSTInt32LSB20, &0x4_0000i32.to_le_bytes()), 0.5);
+        assert_eq!(decode(ASIOSTInt32LSB24, &0x40_0000i32.to_le_bytes()), 0.5);
+        assert_eq!(decode(ASIOSTInt32MSB16, &0x4000i32.to_be_bytes()), 0.5);
+        assert_eq!(decode(ASIOSTInt32MSB18, &0x1_0000i32.to_be_bytes()), 0.5);
+        assert_eq!(decode(ASIOSTInt32MSB20, &0x4_0000i32.to_be_bytes()), 0.5);
+        assert_eq!(decode(ASIOSTInt32MSB24, &0x40_0000i32.to_be_bytes()), 0.5);
+
+        // sign-extended negatives
+        assert_eq!(decode(ASIOSTInt32LSB16, &(-0x8000i32).to_le_bytes()), -1.0);
+        assert_eq!(
+            decode(ASIOSTInt32LSB24, &(-0x20_0000i32).to_le_bytes()),
+            -0.25
+        );
+        assert_eq!(
+            decode(ASIOSTInt32MSB20, &(-1i32).to_be_bytes()),
+            -1.0 / 524_288.0
+        );
+
+        // negatives without sign extension above the sample
+        assert_eq!(decode(ASIOSTInt32LSB16, &0x8000i32.to_le_bytes()), -1.0);
+        assert_eq!(decode(ASIOSTInt32MSB18, &0x2_0000i32.to_be_bytes()), -1.0);
+    }
+
+    #[test]
+    fn float() {
+        assert_eq!(decode(ASIOSTFloat32LSB, &0.25f32.to_le_bytes()), 0.25);
+        assert_eq!(decode(ASIOSTFloat32MSB, &(-0.75f32).to_be_bytes()), -0.75);
+        assert_eq!(decode(ASIOSTFloat64LSB, &0.125f64.to_le_bytes()), 0.125);
+        assert_eq!(decode(ASIOSTFloat64MSB, &(-0.5f64).to_be_bytes()), -0.5);
+    }
+
+    #[test]
+    fn type_codes() {
+        for code in 0..64 {
+            if let Ok(ty) = AsioSampleType::try_from(code) {
+                assert_eq!(ty as i32, code);
+                let dsd = matches!(
+                    ty,
+                    ASIOSTDSDInt8LSB1 | ASIOSTDSDInt8MSB1 | ASIOSTDSDInt8NER8
+                );
+                assert_eq!(ty.decoder().is_none(), dsd, "{:?}", ty);
+            }
+        }
+        assert!(AsioSampleType::try_from(5).is_err());
+        assert!(AsioSampleType::try_from(41).is_err());
+    }
+
+    /// Encode `sample` as `ty` into a buffer of exactly its size
+    fn encode(ty: AsioSampleType, sample: f32) -> Vec<u8> {
+        let mut out = vec![0u8; ty.bytes_per_sample()];
+        ty.encoder().unwrap()(sample, &mut out);
+        out
+    }
+
+    #[test]
+    fn encode_reference() {
+        assert_eq!(encode(ASIOSTInt16LSB, 0.5), [0x00, 0x40]);
+        assert_eq!(encode(ASIOSTInt16MSB, -1.0), [0x80, 0x00]);
+        assert_eq!(encode(ASIOSTInt24LSB, 0.5), [0x00, 0x00, 0x40]);
+        assert_eq!(encode(ASIOSTInt24MSB, -0.5), [0xc0, 0x00, 0x00]);
+        assert_eq!(encode(ASIOSTInt32LSB, -0.25), [0, 0, 0, 0xe0]);
+        assert_eq!(encode(ASIOSTInt32MSB, 0.5), [0x40, 0, 0, 0]);
+        assert_eq!(encode(ASIOSTInt32LSB20, 0.5), 0x4_0000i32.to_le_bytes());
+        assert_eq!(encode(ASIOSTInt32MSB16, -1.0), (-0x8000i32).to_be_bytes());
+        assert_eq!(encode(ASIOSTFloat32MSB, -0.75), (-0.75f32).to_be_bytes());
+        assert_eq!(encode(ASIOSTFloat64LSB, 0.125), 0.125f64.to_le_bytes());
+    }
+
+    #[test]
+    fn encode_clips() {
+        // full scale positive is one step short of 1.0
+        assert_eq!(encode(ASIOSTInt16LSB, 1.0), [0xff, 0x7f]);
+        assert_eq!(encode(ASIOSTInt16LSB, 2.0), [0xff, 0x7f]);
+        assert_eq!(encode(ASIOSTInt24MSB, -2.0), [0x80, 0x00, 0x00]);
+        assert_eq!(encode(ASIOSTInt32LSB, 1.0), i32::MAX.to_le_bytes());
+        assert_eq!(encode(ASIOSTInt32LSB18, 1.5), 0x1_ffffi32.to_le_bytes());
+    }
+
+    #[test]
+    fn round_trip() {
+        for code in 0..64 {
+            let Ok(ty) = AsioSampleType::try_from(code) else {
+                continue;
+            };
+            let Some(decoder) = ty.decoder() else {
+                continue;
+            };
+            for sample in [0.0, 0.5, -0.5, -1.0, 0.25, -0.125] {
+                assert_eq!(decoder(&encode(ty, sample)), sample, "{:?}", ty);
+            }
+        }
+    }
+}
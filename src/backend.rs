@@ -0,0 +1,62 @@
+use anyhow::Result;
+
+use crate::clock::Timeline;
+use crate::ring::{FrameRingConsumer, FrameRingProducer};
+use crate::timestamp::TimestampConsumer;
+
+/// Something that produces interleaved f32 frames, e.g. an ASIO input.
+pub trait AudioSource {
+    /// Nominal rate of the frames pushed into the ring
+    fn sample_rate(&self) -> usize;
+
+    /// Interleaved channels per frame
+    fn channels(&self) -> usize;
+
+    /// Frames delivered per period, used to size the ring
+    fn block_size(&self) -> usize;
+
+    /// Start pushing frames into `ring`
+    fn start(&mut self, ring: FrameRingProducer) -> Result<()>;
+
+    fn stop(&mut self) -> Result<()>;
+
+    /// True once a finite source, like a file, has pushed its last frame
+    fn is_finished(&self) -> bool {
+        false
+    }
+
+    /// Hardware timestamps of the periods pushed after the next `start`, for
+    /// sources that have them. Called before `start`.
+    fn timestamps(&mut self) -> Option<TimestampConsumer> {
+        None
+    }
+}
+
+/// Something that consumes interleaved f32 frames, e.g. a WASAPI render endpoint.
+pub trait AudioSink {
+    /// Rate the sink expects frames to arrive at
+    fn sample_rate(&self) -> usize;
+
+    /// Interleaved channels per frame
+    fn channels(&self) -> usize;
+
+    /// Frames consumed per period, used to size the ring
+    fn block_size(&self) -> usize;
+
+    /// Start pulling frames from `ring`
+    fn start(&mut self, ring: FrameRingConsumer) -> Result<()>;
+
+    fn stop(&mut self) -> Result<()>;
+
+    /// False for sinks that take frames as fast as they come, like an unpaced
+    /// file; the bridge waits for those instead of dropping what they're fed
+    fn is_paced(&self) -> bool {
+        true
+    }
+
+    /// Virtual time a simulated sink is paced by; the resampler feeding it
+    /// then keeps time and takes turns on it too
+    fn timeline(&self) -> Option<Timeline> {
+        None
+    }
+}
@@ -0,0 +1,320 @@
+use anyhow::Result;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::backend::{AudioSink, AudioSource};
+use crate::drift::{DriftConfig, DriftState};
+use crate::resample::{spawn_resampler, ResampleMode, ResamplerConfig};
+use crate::ring::{new_framering, OverflowPolicy, RingStats, RingStatsSnapshot};
+use crate::timestamp::ClockEstimate;
+use crate::util::*;
+
+/// Source ring size used when the builder isn't given one, big enough for bursty asio buffers
+const DEFAULT_SOURCE_RING_FRAMES: usize = 2048;
+
+/// Sink ring size in sink periods used when the builder isn't given one.
+///
+/// Between two clocks the source's periods slowly slide across the sink's, and
+/// each time they cross the ring briefly needs a whole period more or less;
+/// with only two there is no room left for that.
+const DEFAULT_SINK_RING_PERIODS: usize = 3;
+
+/// Longest the resampler waits on a sink that isn't paced before dropping frames
+const UNPACED_SINK_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BridgeState {
+    Stopped,
+    Running,
+}
+
+/// Snapshot of a [`Bridge`] returned by [`Bridge::status`].
+#[derive(Clone, Debug)]
+pub struct BridgeStatus {
+    pub state: BridgeState,
+    pub source_rate: usize,
+    pub sink_rate: usize,
+    pub channels: usize,
+    /// How the resampler moves frames, picked from the rates and drift setting
+    pub resample_mode: ResampleMode,
+    /// False if the resampler thread died while the bridge was running
+    pub resampler_alive: bool,
+    /// The source has run out of frames, see [`AudioSource::is_finished`]
+    pub source_finished: bool,
+    /// Drift controller state, `None` with compensation disabled
+    pub drift: Option<DriftState>,
+    /// Source clock measured from its hardware timestamps, `None` until there
+    /// are enough or if the source has none
+    pub source_clock: Option<ClockEstimate>,
+    /// Counters of the ring the source writes, drops here are the source's
+    pub source_ring: RingStatsSnapshot,
+    /// Counters of the ring the sink reads, short reads here are the sink's
+    pub sink_ring: RingStatsSnapshot,
+}
+
+/// Builder for a [`Bridge`], see [`Bridge::builder`].
+pub struct BridgeBuilder {
+    source: Option<Box<dyn AudioSource>>,
+    sink: Option<Box<dyn AudioSink>>,
+    source_ring_frames: usize,
+    sink_ring_periods: usize,
+    source_overflow: OverflowPolicy,
+    sink_overflow: Option<OverflowPolicy>,
+    drift: Option<DriftConfig>,
+}
+
+impl BridgeBuilder {
+    pub fn source(mut self, source: impl AudioSource + 'static) -> Self {
+        self.source = Some(Box::new(source));
+        self
+    }
+
+    pub fn sink(mut self, sink: impl AudioSink + 'static) -> Self {
+        self.sink = Some(Box::new(sink));
+        self
+    }
+
+    /// Capacity of the ring between the source and the resampler
+    pub fn source_ring_frames(mut self, frames: usize) -> Self {
+        self.source_ring_frames = frames;
+        self
+    }
+
+    /// Capacity of the ring between the resampler and the sink, in sink periods
+    pub fn sink_ring_periods(mut self, periods: usize) -> Self {
+        self.sink_ring_periods = periods;
+        self
+    }
+
+    /// What the source does with frames the source ring has no room for,
+    /// whether it pushes them or writes them in place.
+    ///
+    /// [`OverflowPolicy::DropOldest`] bounds the latency of a source that runs
+    /// ahead. [`OverflowPolicy::Block`] stalls the source's own thread, so it
+    /// is only for sources that aren't driven by a device callback.
+    pub fn source_overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.source_overflow = policy;
+        self
+    }
+
+    /// What the resampler does with frames the sink ring has no room for.
+    ///
+    /// By default it drops the newest for a paced sink and blocks on one that
+    /// isn't, see [`AudioSink::is_paced`].
+    pub fn sink_overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.sink_overflow = Some(policy);
+        self
+    }
+
+    /// Tuning for clock drift compensation, `None` runs the resampler at a fixed ratio
+    pub fn drift_compensation(mut self, drift: Option<DriftConfig>) -> Self {
+        self.drift = drift;
+        self
+    }
+
+    pub fn build(self) -> Result<Bridge> {
+        let Some(source) = self.source else {
+            anyhow::bail!("bridge needs a source");
+        };
+        let Some(sink) = self.sink else {
+            anyhow::bail!("bridge needs a sink");
+        };
+        anyhow::ensure!(
+            source.channels() == sink.channels(),
+            "source has {} channels but sink has {}",
+            source.channels(),
+            sink.channels()
+        );
+        anyhow::ensure!(
+            self.source_ring_frames >= source.block_size(),
+            "source ring of {} frames can't hold a {} frame source period",
+            self.source_ring_frames,
+            source.block_size()
+        );
+        anyhow::ensure!(
+            self.sink_ring_periods > 0,
+            "sink ring needs at least one period"
+        );
+
+        Ok(Bridge {
+            source,
+            sink,
+            source_ring_frames: self.source_ring_frames,
+            sink_ring_periods: self.sink_ring_periods,
+            source_overflow: self.source_overflow,
+            sink_overflow: self.sink_overflow,
+            drift: self.drift,
+            drift_state: Arc::new(Mutex::new(DriftState::default())),
+            source_clock: Arc::new(Mutex::new(None)),
+            ring_stats: None,
+            running: Arc::new(AtomicBool::new(false)),
+            resampler: None,
+        })
+    }
+}
+
+/// A source, resampler and sink wired together through two frame rings.
+///
+/// The bridge owns every stage; dropping it stops them.
+pub struct Bridge {
+    source: Box<dyn AudioSource>,
+    sink: Box<dyn AudioSink>,
+    source_ring_frames: usize,
+    sink_ring_periods: usize,
+    source_overflow: OverflowPolicy,
+    sink_overflow: Option<OverflowPolicy>,
+    drift: Option<DriftConfig>,
+    drift_state: Arc<Mutex<DriftState>>,
+    source_clock: Arc<Mutex<Option<ClockEstimate>>>,
+    /// Source and sink ring counters of the last start
+    ring_stats: Option<(Arc<RingStats>, Arc<RingStats>)>,
+    running: Arc<AtomicBool>,
+    resampler: Option<JoinHandle<()>>,
+}
+
+impl Bridge {
+    pub fn builder() -> BridgeBuilder {
+        BridgeBuilder {
+            source: None,
+            sink: None,
+            source_ring_frames: DEFAULT_SOURCE_RING_FRAMES,
+            sink_ring_periods: DEFAULT_SINK_RING_PERIODS,
+            source_overflow: OverflowPolicy::default(),
+            sink_overflow: None,
+            drift: Some(DriftConfig::default()),
+        }
+    }
+
+    /// Create fresh rings and start the sink, resampler and source.
+    ///
+    /// Stages start downstream first so nothing the source produces is thrown away.
+    pub fn start(&mut self) -> Result<()> {
+        if self.resampler.is_some() {
+            return Ok(());
+        }
+
+        let channels = self.source.channels();
+        let (source_producer, source_consumer) =
+            new_framering(channels, self.source_ring_frames, "source");
+        let source_producer = source_producer.with_overflow_policy(self.source_overflow);
+        let (sink_producer, sink_consumer) = new_framering(
+            channels,
+            self.sink.block_size() * self.sink_ring_periods,
+            "sink",
+        );
+        // with nothing pacing the sink, losing frames to it would only corrupt the output
+        let sink_overflow = self.sink_overflow.unwrap_or(if self.sink.is_paced() {
+            OverflowPolicy::DropNewest
+        } else {
+            OverflowPolicy::Block(UNPACED_SINK_TIMEOUT)
+        });
+        let sink_producer = sink_producer.with_overflow_policy(sink_overflow);
+        self.ring_stats = Some((source_producer.stats(), sink_consumer.stats()));
+
+        self.sink.start(sink_consumer)?;
+
+        *self.drift_state.lock().unwrap() = DriftState::default();
+        *self.source_clock.lock().unwrap() = None;
+        self.running.store(true, Ordering::Relaxed);
+        let resampler = spawn_resampler(
+            source_consumer,
+            sink_producer,
+            self.resampler_config(),
+            self.running.clone(),
+            self.drift_state.clone(),
+            self.source.timestamps(),
+            self.source_clock.clone(),
+            self.sink.timeline(),
+        );
+        match resampler {
+            Ok(handle) => self.resampler = Some(handle),
+            Err(e) => {
+                self.running.store(false, Ordering::Relaxed);
+                let _ = self.sink.stop();
+                return Err(e);
+            }
+        }
+
+        if let Err(e) = self.source.start(source_producer) {
+            let _ = self.stop();
+            return Err(e);
+        }
+
+        info!(
+            "Bridge running: {} Hz -> {} Hz, {} channels",
+            self.source.sample_rate(),
+            self.sink.sample_rate(),
+            channels
+        );
+        Ok(())
+    }
+
+    /// Stop the source, then the resampler, then the sink.
+    ///
+    /// Every stage is stopped even if an earlier one fails; the first error is returned.
+    pub fn stop(&mut self) -> Result<()> {
+        let source = self.source.stop();
+
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(resampler) = self.resampler.take() {
+            let _ = resampler.join();
+        }
+
+        let sink = self.sink.stop();
+        source.and(sink)
+    }
+
+    fn resampler_config(&self) -> ResamplerConfig {
+        ResamplerConfig {
+            input_rate: self.source.sample_rate(),
+            output_rate: self.sink.sample_rate(),
+            channels: self.source.channels(),
+            sink_period: self.sink.block_size(),
+            drift: self.drift,
+        }
+    }
+
+    pub fn status(&self) -> BridgeStatus {
+        let state = if self.resampler.is_some() {
+            BridgeState::Running
+        } else {
+            BridgeState::Stopped
+        };
+        BridgeStatus {
+            state,
+            source_rate: self.source.sample_rate(),
+            sink_rate: self.sink.sample_rate(),
+            channels: self.source.channels(),
+            resample_mode: ResampleMode::select(&self.resampler_config()),
+            resampler_alive: self
+                .resampler
+                .as_ref()
+                .is_some_and(|handle| !handle.is_finished()),
+            source_finished: self.source.is_finished(),
+            drift: self.drift.map(|_| *self.drift_state.lock().unwrap()),
+            source_clock: *self.source_clock.lock().unwrap(),
+            source_ring: self
+                .ring_stats
+                .as_ref()
+                .map_or_else(Default::default, |(source, _)| source.snapshot()),
+            sink_ring: self
+                .ring_stats
+                .as_ref()
+                .map_or_else(Default::default, |(_, sink)| sink.snapshot()),
+        }
+    }
+}
+
+impl Drop for Bridge {
+    fn drop(&mut self) {
+        if let Err(e) = self.stop() {
+            error!("Failed to stop bridge: {:?}", e);
+        }
+    }
+}
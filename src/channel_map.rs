@@ -0,0 +1,167 @@
+use anyhow::Result;
+use std::{fmt, str::FromStr};
+
+/// One device channel, as the user names it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChannelSpec {
+    /// 1-based, the way interfaces label their channels
+    Number(usize),
+    /// The driver's channel name, compared case-insensitively
+    Name(String),
+}
+
+/// Which device channel feeds each bridge channel, in bridge channel order.
+///
+/// Parsed from channels joined by `+` or `,`: `3+4` takes inputs 3 and 4 as a
+/// stereo pair, `Analog 1+Analog 1` duplicates one input onto both sides.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChannelMap {
+    channels: Vec<ChannelSpec>,
+}
+
+impl ChannelMap {
+    /// Number of bridge channels
+    pub fn len(&self) -> usize {
+        self.channels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    /// The first `count` channels of the device, or fewer if it has less
+    pub fn first(count: usize, available: usize) -> Self {
+        Self {
+            channels: (1..=count.min(available))
+                .map(ChannelSpec::Number)
+                .collect(),
+        }
+    }
+
+    /// 0-based device channel for each bridge channel.
+    ///
+    /// `names` are the device's channels in order; the error lists them when a
+    /// channel doesn't exist.
+    pub fn resolve(&self, names: &[String]) -> Result<Vec<usize>> {
+        anyhow::ensure!(!self.channels.is_empty(), "channel map is empty");
+        self.channels
+            .iter()
+            .map(|spec| {
+                let found = match spec {
+                    ChannelSpec::Number(number) => {
+                        (*number >= 1 && *number <= names.len()).then(|| number - 1)
+                    }
+                    ChannelSpec::Name(name) => names
+                        .iter()
+                        .position(|candidate| candidate.eq_ignore_ascii_case(name)),
+                };
+                found.ok_or_else(|| {
+                    let available: Vec<String> = names
+                        .iter()
+                        .enumerate()
+                        .map(|(i, name)| format!("  {}: {}", i + 1, name))
+                        .collect();
+                    anyhow::anyhow!(
+                        "no channel {}\n\nAvailable channels:\n{}",
+                        spec,
+                        available.join("\n")
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+impl FromStr for ChannelMap {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let channels = value
+            .split(['+', ','])
+            .map(|part| {
+                let part = part.trim();
+                anyhow::ensure!(!part.is_empty(), "empty channel in {}", value);
+                Ok(match part.parse::<usize>() {
+                    Ok(0) => anyhow::bail!("channel numbers start at 1"),
+                    Ok(number) => ChannelSpec::Number(number),
+                    Err(_) => ChannelSpec::Name(part.to_string()),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { channels })
+    }
+}
+
+impl fmt::Display for ChannelSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChannelSpec::Number(number) => write!(f, "{}", number),
+            ChannelSpec::Name(name) => write!(f, "\"{}\"", name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names() -> Vec<String> {
+        ["Analog 1", "Analog 2", "Analog 3", "Analog 4", "SPDIF L"]
+            .map(String::from)
+            .to_vec()
+    }
+
+    fn resolve(map: &str) -> Result<Vec<usize>> {
+        map.parse::<ChannelMap>()?.resolve(&names())
+    }
+
+    #[test]
+    fn numbers_pick_1_based_channels() {
+        let map: ChannelMap = "3+4".parse().unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.resolve(&names()).unwrap(), [2, 3]);
+        assert_eq!(resolve(" 4 , 3 ").unwrap(), [3, 2]);
+    }
+
+    #[test]
+    fn zero_is_rejected() {
+        let error = "0".parse::<ChannelMap>().unwrap_err();
+        assert_eq!(error.to_string(), "channel numbers start at 1");
+        assert!("1+0".parse::<ChannelMap>().is_err());
+    }
+
+    #[test]
+    fn names_match_case_insensitively() {
+        assert_eq!(resolve("spdif l").unwrap(), [4]);
+        assert_eq!(resolve("Analog 2+Analog 2").unwrap(), [1, 1]);
+        assert_eq!(resolve("SPDIF L,1").unwrap(), [4, 0]);
+    }
+
+    #[test]
+    fn empty_channel_is_rejected() {
+        let error = "3+".parse::<ChannelMap>().unwrap_err();
+        assert_eq!(error.to_string(), "empty channel in 3+");
+        assert!("".parse::<ChannelMap>().is_err());
+    }
+
+    #[test]
+    fn missing_channel_lists_available() {
+        let available = "\n\nAvailable channels:\n  1: Analog 1\n  2: Analog 2\n  3: Analog 3\n  4: Analog 4\n  5: SPDIF L";
+        let error = resolve("3+6").unwrap_err();
+        assert_eq!(error.to_string(), format!("no channel 6{available}"));
+        let error = resolve("ADAT 1").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("no channel \"ADAT 1\"{available}")
+        );
+    }
+
+    #[test]
+    fn first_stops_at_device_channels() {
+        assert_eq!(ChannelMap::first(2, 8).resolve(&names()).unwrap(), [0, 1]);
+        let map = ChannelMap::first(2, 0);
+        assert!(map.is_empty());
+        let error = map.resolve(&[]).unwrap_err();
+        assert_eq!(error.to_string(), "channel map is empty");
+    }
+}
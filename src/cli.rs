@@ -0,0 +1,115 @@
+use anyhow::{Context, Result};
+use asio_wdm_bridge::{channel_map::ChannelMap, selector::DriverSelector, wasapi::CaptureMode};
+
+const USAGE: &str = "\
+Usage: asio_wdm_bridge [options]
+       asio_wdm_bridge list-devices [--json]
+
+Commands:
+  list-devices          List ASIO drivers and WASAPI endpoints with their capabilities,
+                        as a table or with --json as JSON
+
+Options:
+  --driver <driver>     ASIO driver to open, by index (2), exact name (=Name), regular
+                        expression (/pattern/) or case-insensitive substring
+                        (default: the only installed driver)
+  --inputs <channels>   ASIO inputs feeding the bridge channels, by number (from 1) or
+                        name, joined with + (e.g. 3+4) (default: 1+2)
+  --capture             Run in reverse: record the default WASAPI capture endpoint and
+                        play it on ASIO outputs
+  --loopback            Run in reverse: play what the default WASAPI render endpoint is
+                        playing on ASIO outputs
+  --outputs <channels>  ASIO outputs for the bridge channels when running in reverse, like
+                        --inputs (default: 1+2)
+  --asio-rate <hz>      Switch the ASIO driver to this rate (default: keep its current rate)
+  --wasapi-rate <hz>    Render at this rate (default: the ASIO rate if the device supports it,
+                        otherwise the best rate it supports); in reverse, capture at this
+                        rate (default: the endpoint's mix format rate)
+  -h, --help            Show this help
+";
+
+/// What the binary was asked to do.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub enum Command {
+    /// Run the bridge
+    #[default]
+    Run,
+    ListDevices {
+        json: bool,
+    },
+}
+
+/// Command line options for the bridge binary.
+#[derive(Debug, Default)]
+pub struct Args {
+    pub command: Command,
+    pub driver: Option<DriverSelector>,
+    pub inputs: Option<ChannelMap>,
+    /// Run from WASAPI capture to ASIO outputs instead of ASIO inputs to WASAPI render
+    pub capture: Option<CaptureMode>,
+    pub outputs: Option<ChannelMap>,
+    pub asio_rate: Option<usize>,
+    pub wasapi_rate: Option<usize>,
+}
+
+impl Args {
+    pub fn parse() -> Result<Self> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    pub fn parse_from(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut parsed = Self::default();
+        let mut args = args.into_iter().peekable();
+        if args.peek().map(String::as_str) == Some("list-devices") {
+            args.next();
+            parsed.command = Command::ListDevices { json: false };
+        }
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .with_context(|| format!("{} needs a value\n\n{}", name, USAGE))
+            };
+            match arg.as_str() {
+                "--json" => match &mut parsed.command {
+                    Command::ListDevices { json } => *json = true,
+                    Command::Run => {
+                        anyhow::bail!("--json only applies to list-devices\n\n{}", USAGE)
+                    }
+                },
+                "--driver" => parsed.driver = Some(value(&arg)?.parse()?),
+                "--inputs" => parsed.inputs = Some(value(&arg)?.parse()?),
+                "--capture" => parsed.capture = Some(CaptureMode::Endpoint),
+                "--loopback" => parsed.capture = Some(CaptureMode::Loopback),
+                "--outputs" => parsed.outputs = Some(value(&arg)?.parse()?),
+                "--asio-rate" => parsed.asio_rate = Some(parse_rate(&value(&arg)?)?),
+                "--wasapi-rate" => parsed.wasapi_rate = Some(parse_rate(&value(&arg)?)?),
+                "-h" | "--help" => {
+                    print!("{}", USAGE);
+                    std::process::exit(0);
+                }
+                _ => anyhow::bail!("unknown argument {}\n\n{}", arg, USAGE),
+            }
+        }
+
+        match parsed.capture {
+            Some(_) => anyhow::ensure!(
+                parsed.inputs.is_none(),
+                "--inputs doesn't apply with --capture or --loopback, use --outputs\n\n{}",
+                USAGE
+            ),
+            None => anyhow::ensure!(
+                parsed.outputs.is_none(),
+                "--outputs needs --capture or --loopback\n\n{}",
+                USAGE
+            ),
+        }
+        Ok(parsed)
+    }
+}
+
+fn parse_rate(value: &str) -> Result<usize> {
+    match value.parse() {
+        Ok(rate) if rate > 0 => Ok(rate),
+        _ => anyhow::bail!("invalid sample rate {}", value),
+    }
+}
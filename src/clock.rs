@@ -0,0 +1,284 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+/// How often a member blocked on a [`Timeline`] checks whether it should stop
+const TIMELINE_POLL: Duration = Duration::from_millis(10);
+
+/// How a file or simulated backend spaces out its periods.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Pacing {
+    /// One period per period of wall-clock time, like a hardware device
+    RealTime,
+    /// Periods come due as a shared virtual timeline is advanced, see [`Timeline`]
+    Virtual(Timeline),
+    /// No waiting at all; producers wait for ring space and consumers for data
+    /// instead, see [`AudioSink::is_paced`](crate::backend::AudioSink::is_paced)
+    Unpaced,
+}
+
+impl Pacing {
+    /// The timeline of virtual pacing
+    pub fn timeline(&self) -> Option<Timeline> {
+        match self {
+            Pacing::Virtual(timeline) => Some(timeline.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Period timer for backends that have no hardware clock of their own.
+///
+/// Deadlines are derived from the total frame count since the clock started, so
+/// late wakeups don't accumulate into drift.
+pub struct Clock {
+    pacing: Pacing,
+    rate: f64,
+    drift: f64,
+    start: Instant,
+    /// Joined when the clock is created, so the timeline waits for its first period
+    member: Option<TimelineMember>,
+    /// Where the timeline was when the clock started
+    virtual_start: Duration,
+    running: Option<Arc<AtomicBool>>,
+    frames: u64,
+}
+
+impl Clock {
+    pub fn new(pacing: Pacing, rate: usize) -> Self {
+        let member = pacing.timeline().map(|timeline| timeline.join());
+        Self {
+            pacing,
+            rate: rate as f64,
+            drift: 1.0,
+            start: Instant::now(),
+            member,
+            virtual_start: Duration::ZERO,
+            running: None,
+            frames: 0,
+        }
+    }
+
+    /// Run the clock `ppm` parts per million fast (or slow, if negative) against
+    /// its nominal rate, like a free-running crystal.
+    pub fn with_drift_ppm(mut self, ppm: f64) -> Self {
+        self.drift = 1.0 + ppm * 1e-6;
+        self
+    }
+
+    /// Give up waiting once `running` is cleared; a virtual timeline may
+    /// otherwise never get to the next period
+    pub fn with_running(mut self, running: Arc<AtomicBool>) -> Self {
+        self.running = Some(running);
+        self
+    }
+
+    pub fn pacing(&self) -> &Pacing {
+        &self.pacing
+    }
+
+    /// Whether periods follow a timeline; unpaced backends must never drop or pad frames
+    pub fn is_paced(&self) -> bool {
+        self.pacing != Pacing::Unpaced
+    }
+
+    /// Restart the timeline from now
+    pub fn reset(&mut self) {
+        self.start = Instant::now();
+        if let Some(member) = &self.member {
+            self.virtual_start = member.now();
+        }
+        self.frames = 0;
+    }
+
+    /// Time on this clock's nominal timeline covered by the periods so far
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_secs_f64(self.frames as f64 / self.rate)
+    }
+
+    /// Account for a period of `frames` and block until it is due.
+    pub fn wait(&mut self, frames: usize) {
+        self.frames += frames as u64;
+        if self.pacing == Pacing::Unpaced {
+            return;
+        }
+
+        let due = self.elapsed().div_f64(self.drift);
+        if let Some(member) = &self.member {
+            let running = self.running.as_deref();
+            member.wait_until(self.virtual_start + due, || {
+                running.is_none_or(|running| running.load(Ordering::Relaxed))
+            });
+            return;
+        }
+        let due = self.start + due;
+        let now = Instant::now();
+        if due > now {
+            std::thread::sleep(due - now);
+        }
+    }
+}
+
+/// Virtual time shared by simulated backends, moved on only by [`advance`](Self::advance).
+///
+/// Members take turns: each runs its period once the timeline gets to it, one
+/// at a time in deadline and then joining order, and time only moves on once
+/// the member whose turn it was is waiting again and every idle member, like a
+/// resampler, has caught up with it. Runs on a timeline are repeatable however
+/// loaded the machine is.
+#[derive(Clone, Debug, Default)]
+pub struct Timeline {
+    shared: Arc<(Mutex<TimelineState>, Condvar)>,
+}
+
+#[derive(Debug, Default)]
+struct TimelineState {
+    now: Duration,
+    next_id: u64,
+    members: usize,
+    /// Deadlines members are waiting for, with their ids
+    deadlines: Vec<(Duration, u64)>,
+    /// The member whose deadline the timeline stopped at
+    turn: Option<u64>,
+    /// Bumped every time a member is done with its turn
+    epoch: u64,
+    /// The epoch each idle member saw last
+    idle: Vec<u64>,
+}
+
+impl TimelineState {
+    /// Every member is waiting, none for its turn, and the idle ones are up to date
+    fn settled(&self) -> bool {
+        self.turn.is_none()
+            && self.deadlines.len() + self.idle.len() == self.members
+            && self.idle.iter().all(|&epoch| epoch == self.epoch)
+    }
+}
+
+impl PartialEq for Timeline {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.shared, &other.shared)
+    }
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn now(&self) -> Duration {
+        self.lock().now
+    }
+
+    /// Take part in the timeline; it waits for the member until it is dropped
+    pub fn join(&self) -> TimelineMember {
+        let mut state = self.lock();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.members += 1;
+        TimelineMember {
+            timeline: self.clone(),
+            id,
+        }
+    }
+
+    /// Move time on by `by`, giving every member whose deadline falls within
+    /// it its turn on the way.
+    ///
+    /// Returns once the members are all waiting for later deadlines.
+    pub fn advance(&self, by: Duration) {
+        let (_, changed) = &*self.shared;
+        let mut state = self.lock();
+        let target = state.now + by;
+        loop {
+            while !state.settled() {
+                state = changed.wait(state).unwrap();
+            }
+            let Some(&(due, id)) = state.deadlines.iter().min() else {
+                break;
+            };
+            if due > target {
+                break;
+            }
+            state.now = state.now.max(due);
+            state.turn = Some(id);
+            changed.notify_all();
+        }
+        state.now = target;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, TimelineState> {
+        self.shared.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A backend's or resampler's part in a [`Timeline`], see [`Timeline::join`].
+#[derive(Debug)]
+pub struct TimelineMember {
+    timeline: Timeline,
+    id: u64,
+}
+
+impl TimelineMember {
+    pub fn now(&self) -> Duration {
+        self.timeline.now()
+    }
+
+    /// Count of turns taken so far, to hand to [`idle`](Self::idle)
+    pub fn epoch(&self) -> u64 {
+        self.timeline.lock().epoch
+    }
+
+    /// Block until the timeline gets to `due` and gives this member its turn,
+    /// or until `running` returns false
+    pub fn wait_until(&self, due: Duration, running: impl Fn() -> bool) {
+        let (_, changed) = &*self.timeline.shared;
+        let mut state = self.timeline.lock();
+        state.deadlines.push((due, self.id));
+        state.epoch += 1;
+        changed.notify_all();
+        while state.turn != Some(self.id) && running() {
+            state = changed.wait_timeout(state, TIMELINE_POLL).unwrap().0;
+        }
+        if state.turn == Some(self.id) {
+            state.turn = None;
+        }
+        state.deadlines.retain(|&(_, id)| id != self.id);
+        changed.notify_all();
+    }
+
+    /// Wait for another member to take a turn after `epoch`, or a moment at
+    /// most; a member without deadlines calls this when it runs out of work
+    pub fn idle(&self, epoch: u64) {
+        let (_, changed) = &*self.timeline.shared;
+        let mut state = self.timeline.lock();
+        if state.epoch != epoch {
+            return;
+        }
+        state.idle.push(epoch);
+        changed.notify_all();
+        let (mut state, _) = changed
+            .wait_timeout_while(state, TIMELINE_POLL, |state| state.epoch == epoch)
+            .unwrap();
+        if let Some(i) = state.idle.iter().position(|&seen| seen == epoch) {
+            state.idle.swap_remove(i);
+        }
+    }
+}
+
+impl Drop for TimelineMember {
+    fn drop(&mut self) {
+        let (_, changed) = &*self.timeline.shared;
+        let mut state = self.timeline.lock();
+        state.members -= 1;
+        state.deadlines.retain(|&(_, id)| id != self.id);
+        if state.turn == Some(self.id) {
+            state.turn = None;
+        }
+        changed.notify_all();
+    }
+}
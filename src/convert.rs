@@ -0,0 +1,47 @@
+/// Convert f32 samples to the hardware format (optimized)
+pub fn convert_samples_to_bytes(
+    samples: &[f32],
+    byte_buffer: &mut Vec<u8>,
+    bits_per_sample: u16,
+    is_float: bool,
+) {
+    byte_buffer.clear();
+    append_samples_as_bytes(samples, byte_buffer, bits_per_sample, is_float);
+}
+
+/// Like `convert_samples_to_bytes`, adding to what `byte_buffer` already holds
+pub fn append_samples_as_bytes(
+    samples: &[f32],
+    byte_buffer: &mut Vec<u8>,
+    bits_per_sample: u16,
+    is_float: bool,
+) {
+    if bits_per_sample == 32 && is_float {
+        // Fast path: direct memory copy for f32
+        let bytes =
+            unsafe { std::slice::from_raw_parts(samples.as_ptr() as *const u8, samples.len() * 4) };
+        byte_buffer.extend_from_slice(bytes);
+    } else {
+        match bits_per_sample {
+            16 => {
+                for &sample in samples {
+                    let val = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+                    byte_buffer.extend_from_slice(&val.to_le_bytes());
+                }
+            }
+            24 => {
+                for &sample in samples {
+                    let val = (sample.clamp(-1.0, 1.0) * 8_388_607.0) as i32;
+                    byte_buffer.extend_from_slice(&val.to_le_bytes()[..3]);
+                }
+            }
+            32 => {
+                for &sample in samples {
+                    let val = (sample.clamp(-1.0, 1.0) * i32::MAX as f32) as i32;
+                    byte_buffer.extend_from_slice(&val.to_le_bytes());
+                }
+            }
+            _ => unreachable!("Unsupported bit depth: {}", bits_per_sample),
+        }
+    }
+}
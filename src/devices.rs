@@ -0,0 +1,385 @@
+use std::fmt::Write;
+
+/// Buffer sizes an ASIO driver accepts, in frames.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BufferSizeRange {
+    pub min: usize,
+    pub max: usize,
+    pub preferred: usize,
+    /// Step between sizes, -1 means powers of two
+    pub granularity: i64,
+}
+
+/// What an installed ASIO driver reports when it is loaded.
+#[derive(Clone, Debug, Default)]
+pub struct AsioDriverInfo {
+    /// Position in the driver enumeration, usable as `--driver <index>`
+    pub index: usize,
+    pub name: String,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub buffer_size: BufferSizeRange,
+    /// Rate the driver is currently clocked at
+    pub sample_rate: usize,
+    /// Which of the probed rates the driver accepts
+    pub supported_rates: Vec<usize>,
+    /// Why the driver couldn't be queried, the other fields are empty if set
+    pub error: Option<String>,
+}
+
+/// Whether a WASAPI endpoint plays or records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EndpointDirection {
+    Render,
+    Capture,
+}
+
+impl EndpointDirection {
+    fn as_str(self) -> &'static str {
+        match self {
+            EndpointDirection::Render => "render",
+            EndpointDirection::Capture => "capture",
+        }
+    }
+}
+
+/// An active WASAPI endpoint with its shared-mode format and timing.
+#[derive(Clone, Debug)]
+pub struct WasapiEndpointInfo {
+    pub direction: EndpointDirection,
+    pub name: String,
+    pub id: String,
+    /// The default endpoint for its direction
+    pub is_default: bool,
+    pub mix_rate: usize,
+    pub mix_channels: usize,
+    pub mix_bits: u16,
+    pub mix_float: bool,
+    /// Default and minimum device periods, in 100 ns units
+    pub default_period: i64,
+    pub min_period: i64,
+    /// Why the endpoint couldn't be queried, the format fields are empty if set
+    pub error: Option<String>,
+}
+
+/// Everything `list-devices` found, printable as a table or JSON.
+#[derive(Clone, Debug, Default)]
+pub struct DeviceList {
+    pub asio: Vec<AsioDriverInfo>,
+    pub wasapi: Vec<WasapiEndpointInfo>,
+}
+
+impl DeviceList {
+    pub fn to_table(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("ASIO drivers\n");
+        if self.asio.is_empty() {
+            out.push_str("  (none)\n");
+        }
+        for driver in &self.asio {
+            let _ = writeln!(out, "  {}: {}", driver.index, driver.name);
+            if let Some(error) = &driver.error {
+                let _ = writeln!(out, "      error: {}", error);
+                continue;
+            }
+            let buffer = driver.buffer_size;
+            let _ = writeln!(
+                out,
+                "      buffer      {}..{} frames, preferred {}, granularity {}",
+                buffer.min, buffer.max, buffer.preferred, buffer.granularity
+            );
+            let _ = writeln!(
+                out,
+                "      rates       {} Hz current, supports {}",
+                driver.sample_rate,
+                join(&driver.supported_rates)
+            );
+            let _ = writeln!(out, "      inputs      {}", driver.inputs.len());
+            for (i, name) in driver.inputs.iter().enumerate() {
+                let _ = writeln!(out, "        {:>3}  {}", i + 1, name);
+            }
+            let _ = writeln!(out, "      outputs     {}", driver.outputs.len());
+            for (i, name) in driver.outputs.iter().enumerate() {
+                let _ = writeln!(out, "        {:>3}  {}", i + 1, name);
+            }
+        }
+
+        out.push_str("\nWASAPI endpoints\n");
+        if self.wasapi.is_empty() {
+            out.push_str("  (none)\n");
+        } else {
+            let _ = writeln!(
+                out,
+                "  {:<8} {:<3} {:>7} {:>3} {:>12} {:>9} {:>9}  name",
+                "dir", "def", "rate", "ch", "format", "period", "min"
+            );
+        }
+        for endpoint in &self.wasapi {
+            let default = if endpoint.is_default { "*" } else { "" };
+            if let Some(error) = &endpoint.error {
+                let _ = writeln!(
+                    out,
+                    "  {:<8} {:<3} {}  (error: {})",
+                    endpoint.direction.as_str(),
+                    default,
+                    endpoint.name,
+                    error
+                );
+                continue;
+            }
+            let format = format!(
+                "{}-bit {}",
+                endpoint.mix_bits,
+                if endpoint.mix_float { "float" } else { "int" }
+            );
+            let _ = writeln!(
+                out,
+                "  {:<8} {:<3} {:>7} {:>3} {:>12} {:>7.2}ms {:>7.2}ms  {}",
+                endpoint.direction.as_str(),
+                default,
+                endpoint.mix_rate,
+                endpoint.mix_channels,
+                format,
+                endpoint.default_period as f64 / 10_000.0,
+                endpoint.min_period as f64 / 10_000.0,
+                endpoint.name
+            );
+        }
+        out
+    }
+
+    pub fn to_json(&self) -> String {
+        let asio: Vec<String> = self
+            .asio
+            .iter()
+            .map(|driver| {
+                let buffer = driver.buffer_size;
+                format!(
+                    "{{\"index\":{},\"name\":{},\"error\":{},\"inputs\":[{}],\"outputs\":[{}],\
+                     \"buffer_size\":{{\"min\":{},\"max\":{},\"preferred\":{},\"granularity\":{}}},\
+                     \"sample_rate\":{},\"supported_rates\":[{}]}}",
+                    driver.index,
+                    json_string(&driver.name),
+                    json_option(&driver.error),
+                    json_strings(&driver.inputs),
+                    json_strings(&driver.outputs),
+                    buffer.min,
+                    buffer.max,
+                    buffer.preferred,
+                    buffer.granularity,
+                    driver.sample_rate,
+                    join_with(&driver.supported_rates, ",")
+                )
+            })
+            .collect();
+
+        let wasapi: Vec<String> = self
+            .wasapi
+            .iter()
+            .map(|endpoint| {
+                format!(
+                    "{{\"direction\":\"{}\",\"name\":{},\"id\":{},\"default\":{},\"error\":{},\
+                     \"mix_format\":{{\"rate\":{},\"channels\":{},\"bits\":{},\"float\":{}}},\
+                     \"default_period_hns\":{},\"min_period_hns\":{}}}",
+                    endpoint.direction.as_str(),
+                    json_string(&endpoint.name),
+                    json_string(&endpoint.id),
+                    endpoint.is_default,
+                    json_option(&endpoint.error),
+                    endpoint.mix_rate,
+                    endpoint.mix_channels,
+                    endpoint.mix_bits,
+                    endpoint.mix_float,
+                    endpoint.default_period,
+                    endpoint.min_period
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\"asio\":[{}],\"wasapi\":[{}]}}",
+            asio.join(","),
+            wasapi.join(",")
+        )
+    }
+}
+
+fn join(rates: &[usize]) -> String {
+    if rates.is_empty() {
+        return "none of the probed rates".to_string();
+    }
+    join_with(rates, ", ")
+}
+
+fn join_with(values: &[usize], separator: &str) -> String {
+    values
+        .iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn json_strings(values: &[String]) -> String {
+    values
+        .iter()
+        .map(|value| json_string(value))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn json_option(value: &Option<String>) -> String {
+    value.as_deref().map_or("null".to_string(), json_string)
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn devices() -> DeviceList {
+        DeviceList {
+            asio: vec![
+                AsioDriverInfo {
+                    index: 0,
+                    name: "Fireface \"UFX\"".to_string(),
+                    inputs: vec!["Analog 1".to_string(), "AES\\L".to_string()],
+                    outputs: vec!["Main L".to_string()],
+                    buffer_size: BufferSizeRange {
+                        min: 32,
+                        max: 2048,
+                        preferred: 256,
+                        granularity: -1,
+                    },
+                    sample_rate: 48_000,
+                    supported_rates: vec![44_100, 48_000],
+                    error: None,
+                },
+                AsioDriverInfo {
+                    index: 1,
+                    name: "Broken".to_string(),
+                    error: Some("ASIOInit failed".to_string()),
+                    ..Default::default()
+                },
+            ],
+            wasapi: vec![
+                WasapiEndpointInfo {
+                    direction: EndpointDirection::Render,
+                    name: "Speakers".to_string(),
+                    id: "{0.0.0}".to_string(),
+                    is_default: true,
+                    mix_rate: 48_000,
+                    mix_channels: 2,
+                    mix_bits: 32,
+                    mix_float: true,
+                    default_period: 100_000,
+                    min_period: 30_000,
+                    error: None,
+                },
+                WasapiEndpointInfo {
+                    direction: EndpointDirection::Capture,
+                    name: "Line In".to_string(),
+                    id: "{0.0.1}".to_string(),
+                    is_default: false,
+                    mix_rate: 0,
+                    mix_channels: 0,
+                    mix_bits: 0,
+                    mix_float: false,
+                    default_period: 0,
+                    min_period: 0,
+                    error: Some("device in use".to_string()),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn json_string_escapes() {
+        assert_eq!(json_string("plain"), "\"plain\"");
+        assert_eq!(json_string("say \"hi\""), r#""say \"hi\"""#);
+        assert_eq!(json_string(r"C:\ASIO\"), r#""C:\\ASIO\\""#);
+        assert_eq!(json_string("a\nb\r\tc"), r#""a\nb\r\tc""#);
+        assert_eq!(json_string("\0\u{1b}\u{1f}"), r#""\u0000\u001b\u001f""#);
+        // DEL and anything past ASCII go through as they are
+        assert_eq!(json_string("\u{7f}Ü 🎧"), "\"\u{7f}Ü 🎧\"");
+    }
+
+    #[test]
+    fn json_shape() {
+        let expected = concat!(
+            r#"{"asio":["#,
+            r#"{"index":0,"name":"Fireface \"UFX\"","error":null,"#,
+            r#""inputs":["Analog 1","AES\\L"],"outputs":["Main L"],"#,
+            r#""buffer_size":{"min":32,"max":2048,"preferred":256,"granularity":-1},"#,
+            r#""sample_rate":48000,"supported_rates":[44100,48000]},"#,
+            r#"{"index":1,"name":"Broken","error":"ASIOInit failed","#,
+            r#""inputs":[],"outputs":[],"#,
+            r#""buffer_size":{"min":0,"max":0,"preferred":0,"granularity":0},"#,
+            r#""sample_rate":0,"supported_rates":[]}"#,
+            r#"],"wasapi":["#,
+            r#"{"direction":"render","name":"Speakers","id":"{0.0.0}","default":true,"error":null,"#,
+            r#""mix_format":{"rate":48000,"channels":2,"bits":32,"float":true},"#,
+            r#""default_period_hns":100000,"min_period_hns":30000},"#,
+            r#"{"direction":"capture","name":"Line In","id":"{0.0.1}","default":false,"#,
+            r#""error":"device in use","#,
+            r#""mix_format":{"rate":0,"channels":0,"bits":0,"float":false},"#,
+            r#""default_period_hns":0,"min_period_hns":0}"#,
+            r#"]}"#,
+        );
+        assert_eq!(devices().to_json(), expected);
+        assert_eq!(
+            DeviceList::default().to_json(),
+            r#"{"asio":[],"wasapi":[]}"#
+        );
+    }
+
+    #[test]
+    fn table_shape() {
+        let expected = "\
+ASIO drivers
+  0: Fireface \"UFX\"
+      buffer      32..2048 frames, preferred 256, granularity -1
+      rates       48000 Hz current, supports 44100, 48000
+      inputs      2
+          1  Analog 1
+          2  AES\\L
+      outputs     1
+          1  Main L
+  1: Broken
+      error: ASIOInit failed
+
+WASAPI endpoints
+  dir      def    rate  ch       format    period       min  name
+  render   *     48000   2 32-bit float   10.00ms    3.00ms  Speakers
+  capture      Line In  (error: device in use)
+";
+        assert_eq!(devices().to_table(), expected);
+    }
+
+    #[test]
+    fn empty_table() {
+        assert_eq!(
+            DeviceList::default().to_table(),
+            "ASIO drivers\n  (none)\n\nWASAPI endpoints\n  (none)\n"
+        );
+    }
+}
@@ -0,0 +1,536 @@
+use std::time::Duration;
+
+/// Tuning for a [`DriftController`].
+///
+/// The error is measured in seconds of audio, so the gains don't depend on the rate.
+#[derive(Clone, Copy, Debug)]
+pub struct DriftConfig {
+    /// Time-averaged fill level to hold, as a fraction of the sink ring's capacity
+    pub target_fill: f64,
+    /// Ratio correction per second of fill error
+    pub kp: f64,
+    /// Ratio correction per second of accumulated fill error per second
+    pub ki: f64,
+    /// Largest correction applied either way, in parts per million
+    pub max_ppm: f64,
+    /// Time constant of the low-pass filter on the measured fill level
+    pub smoothing_secs: f64,
+    /// Fill error, in seconds, past which the ring is padded or drained
+    /// straight back to the target after an underrun or overflow instead of
+    /// being steered there
+    pub resync_secs: f64,
+}
+
+impl Default for DriftConfig {
+    fn default() -> Self {
+        Self {
+            target_fill: 0.5,
+            kp: 0.4,
+            ki: 0.04,
+            max_ppm: 1000.0,
+            smoothing_secs: 0.5,
+            resync_secs: 0.005,
+        }
+    }
+}
+
+/// Snapshot of a [`DriftController`], for logging.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DriftState {
+    /// Frames buffered toward the sink averaged over the last sink period
+    pub fill_frames: f64,
+    /// Low-passed fill level the controller acts on, in frames
+    pub filtered_frames: f64,
+    pub target_frames: usize,
+    /// Filtered fill minus target, in seconds
+    pub error_secs: f64,
+    /// Integral of the error, in second-seconds
+    pub integral: f64,
+    /// Relative ratio handed to the resampler, 1.0 is nominal
+    pub ratio: f64,
+    /// Times the ring was padded or drained straight back to the target
+    pub realigns: u64,
+}
+
+impl DriftState {
+    /// Current correction in parts per million
+    pub fn ppm(&self) -> f64 {
+        (self.ratio - 1.0) * 1e6
+    }
+}
+
+/// PI controller that nudges the resampler ratio to hold the sink ring at a target fill.
+///
+/// A sink clock running slower than the source's makes the ring fill up, which
+/// pushes the ratio below 1.0 so fewer frames are produced, and the reverse.
+pub struct DriftController {
+    config: DriftConfig,
+    rate: f64,
+    state: DriftState,
+    primed: bool,
+}
+
+impl DriftController {
+    /// `capacity` is the sink ring size and `rate` its sample rate
+    pub fn new(config: DriftConfig, capacity: usize, rate: usize) -> Self {
+        let target_frames = (capacity as f64 * config.target_fill).round() as usize;
+        Self {
+            config,
+            rate: rate as f64,
+            state: DriftState {
+                target_frames,
+                filtered_frames: target_frames as f64,
+                ratio: 1.0,
+                ..Default::default()
+            },
+            primed: false,
+        }
+    }
+
+    /// Feed the fill level averaged over the `dt` seconds since the previous
+    /// one and return the relative ratio to apply.
+    pub fn update(&mut self, fill_frames: f64, dt: f64) -> f64 {
+        let state = &mut self.state;
+        state.fill_frames = fill_frames;
+
+        if !self.primed {
+            // start from the real level rather than dragging the filter up from the target
+            state.filtered_frames = fill_frames;
+            self.primed = true;
+        } else {
+            let alpha = dt / (self.config.smoothing_secs + dt);
+            state.filtered_frames += alpha * (fill_frames - state.filtered_frames);
+        }
+
+        state.error_secs = (state.filtered_frames - state.target_frames as f64) / self.rate;
+
+        let limit = self.config.max_ppm * 1e-6;
+        let integral = state.integral + state.error_secs * dt;
+        let correction = self.config.kp * state.error_secs + self.config.ki * integral;
+        // only integrate while unsaturated so the integral can't wind up
+        if correction.abs() < limit {
+            state.integral = integral;
+        }
+
+        let correction = (self.config.kp * state.error_secs + self.config.ki * state.integral)
+            .clamp(-limit, limit);
+        state.ratio = 1.0 - correction;
+        state.ratio
+    }
+
+    /// Frames to pad the ring with, or to drop from it if negative, to put it
+    /// straight back on the target.
+    ///
+    /// Where the ring starts out depends on how the source's first periods
+    /// land against the sink's, and an underrun or overflow moves it by up to
+    /// a period for good; steering that back at the correction limit would
+    /// take tens of seconds. The caller asks after the first window and after
+    /// each such glitch, and anything more than [`DriftConfig::resync_secs`]
+    /// off is closed in one go, keeping the integral and with it the drift
+    /// learned so far. Pass the fill plus the gap on to [`update`](Self::update).
+    pub fn realign(&mut self, fill_frames: f64) -> isize {
+        let target = self.state.target_frames as f64;
+        if self.primed && (fill_frames - target).abs() <= self.config.resync_secs * self.rate {
+            return 0;
+        }
+
+        self.primed = true;
+        self.state.filtered_frames = target;
+        let gap = (target - fill_frames).round() as isize;
+        if gap != 0 {
+            self.state.realigns += 1;
+        }
+        gap
+    }
+
+    pub fn state(&self) -> DriftState {
+        self.state
+    }
+}
+
+/// Time-weighted average of how many frames are buffered toward the sink.
+///
+/// The level swings by a whole period between reads, so only its average over
+/// a sink period says how far apart the two clocks are.
+#[derive(Debug, Default)]
+pub struct FillMeter {
+    last: Option<(Duration, usize)>,
+    weighted: f64,
+    secs: f64,
+}
+
+impl FillMeter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Note the fill level at time `at`, held until the next sample
+    pub fn sample(&mut self, at: Duration, fill_frames: usize) {
+        if let Some((last_at, level)) = self.last {
+            let secs = at.saturating_sub(last_at).as_secs_f64();
+            self.weighted += level as f64 * secs;
+            self.secs += secs;
+        }
+        self.last = Some((at, fill_frames));
+    }
+
+    /// Average fill since the previous call, `None` if no time has passed
+    pub fn take(&mut self) -> Option<f64> {
+        let average = (self.secs > 0.0).then(|| self.weighted / self.secs);
+        self.weighted = 0.0;
+        self.secs = 0.0;
+        average
+    }
+}
+
+/// What a [`DriftTracker`] wants done at the end of a sink period.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DriftStep {
+    /// Silent frames to push now, or if negative frames to leave out of what
+    /// is resampled next, see [`DriftController::realign`]
+    pub gap: isize,
+    /// Relative ratio to hand the resampler
+    pub ratio: f64,
+}
+
+/// Runs a [`DriftController`] once per sink period on the fill averaged over it.
+///
+/// Windows run from one sink read to the next at least a sink period later,
+/// so the level is always taken over the same stretch of the sink's period.
+pub struct DriftTracker {
+    controller: DriftController,
+    meter: FillMeter,
+    rate: f64,
+    sink_period: u64,
+    /// Frames the sink had read when the current window opened
+    window_start: Option<u64>,
+    /// Glitch count the last window ended on
+    seen_glitches: u64,
+    /// Realigned since the last glitch
+    aligned: bool,
+    /// Output frames still to leave out to drain the ring down to the target
+    surplus: usize,
+    draining: bool,
+}
+
+impl DriftTracker {
+    /// `capacity` is the sink ring size, `rate` and `sink_period` the sink's
+    pub fn new(config: DriftConfig, capacity: usize, rate: usize, sink_period: usize) -> Self {
+        Self {
+            controller: DriftController::new(config, capacity, rate),
+            meter: FillMeter::new(),
+            rate: rate as f64,
+            sink_period: sink_period as u64,
+            window_start: None,
+            seen_glitches: 0,
+            aligned: false,
+            surplus: 0,
+            draining: false,
+        }
+    }
+
+    /// Note the frames buffered toward the sink at time `at`
+    pub fn sample(&mut self, at: Duration, fill_frames: usize) {
+        self.meter.sample(at, fill_frames);
+    }
+
+    /// Check for the end of a window, given the frames the sink has `read` so
+    /// far and the count of underruns and overflows on either ring.
+    ///
+    /// No window opens before the sink's first read, where the ring settles
+    /// until then depends on the startup race. The ring is realigned on the
+    /// first window and on the first whole window after each glitch.
+    pub fn step(&mut self, read: u64, glitches: u64) -> Option<DriftStep> {
+        let start = match self.window_start {
+            None if read > 0 => {
+                self.meter.take();
+                self.window_start = Some(read);
+                return None;
+            }
+            Some(start) if read - start >= self.sink_period => start,
+            _ => return None,
+        };
+        self.window_start = Some(read);
+        let fill = self.meter.take();
+
+        if self.draining {
+            // only a window that starts after the surplus is gone counts
+            self.draining = self.surplus > 0;
+            return None;
+        }
+        if glitches != self.seen_glitches {
+            self.seen_glitches = glitches;
+            self.aligned = false;
+            return None;
+        }
+        let fill = fill?;
+
+        let gap = if self.aligned {
+            0
+        } else {
+            self.aligned = true;
+            self.controller.realign(fill)
+        };
+        if gap < 0 {
+            self.surplus = gap.unsigned_abs();
+            self.draining = true;
+        }
+        let dt = (read - start) as f64 / self.rate;
+        let ratio = self.controller.update(fill + gap as f64, dt);
+        Some(DriftStep { gap, ratio })
+    }
+
+    /// How many of the next `frames` resampled frames to leave out
+    pub fn skip(&mut self, frames: usize) -> usize {
+        let skipped = self.surplus.min(frames);
+        self.surplus -= skipped;
+        skipped
+    }
+
+    pub fn state(&self) -> DriftState {
+        self.controller.state()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bridge::BridgeStatus;
+    use crate::sim::tests::run_drifting;
+
+    const RATE: usize = 48_000;
+    /// Sink period and ring size of the synthetic runs, the target is 6144 frames
+    const PERIOD: usize = 4096;
+    const CAPACITY: usize = 3 * PERIOD;
+    const TARGET: usize = CAPACITY / 2;
+
+    fn controller() -> DriftController {
+        DriftController::new(DriftConfig::default(), CAPACITY, RATE)
+    }
+
+    /// Frames for `secs` of audio
+    fn frames(secs: f64) -> f64 {
+        secs * RATE as f64
+    }
+
+    #[test]
+    fn fuller_ring_lowers_the_ratio() {
+        let mut controller = controller();
+        // a millisecond over the target: kp * 0.001 + ki * 0.001 * 0.1
+        let ratio = controller.update(TARGET as f64 + frames(0.001), 0.1);
+        assert!((ratio - (1.0 - 404e-6)).abs() < 1e-9, "{ratio}");
+
+        let mut controller = self::controller();
+        let ratio = controller.update(TARGET as f64 - frames(0.001), 0.1);
+        assert!((ratio - (1.0 + 404e-6)).abs() < 1e-9, "{ratio}");
+    }
+
+    #[test]
+    fn integral_grows_with_a_steady_error() {
+        let mut controller = controller();
+        let fill = TARGET as f64 + frames(0.0005);
+        let first = controller.update(fill, 0.1);
+        let second = controller.update(fill, 0.1);
+        assert!(second < first);
+        assert!((controller.state().integral - 0.0001).abs() < 1e-12);
+    }
+
+    #[test]
+    fn correction_clamps_at_max_ppm() {
+        let mut controller = controller();
+        let ratio = controller.update(TARGET as f64 + frames(0.1), 0.1);
+        assert!((ratio - (1.0 - 1000e-6)).abs() < 1e-12, "{ratio}");
+        let mut controller = self::controller();
+        controller.update(0.0, 0.1);
+        assert!((controller.state().ppm() - 1000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn integral_holds_while_saturated() {
+        let mut controller = controller();
+        for _ in 0..100 {
+            controller.update(TARGET as f64 + frames(0.1), 0.1);
+        }
+        assert_eq!(controller.state().integral, 0.0);
+
+        // back on target the correction goes straight away instead of unwinding
+        assert_eq!(controller.realign(TARGET as f64 + frames(0.1)), -4800);
+        assert_eq!(controller.update(TARGET as f64, 0.1), 1.0);
+    }
+
+    #[test]
+    fn smoothing_filters_the_fill() {
+        let mut controller = controller();
+        controller.update(TARGET as f64, 0.5);
+        controller.update(TARGET as f64 + 100.0, 0.5);
+        // alpha = dt / (smoothing_secs + dt) = 0.5
+        assert_eq!(controller.state().filtered_frames, TARGET as f64 + 50.0);
+        assert_eq!(controller.state().fill_frames, TARGET as f64 + 100.0);
+    }
+
+    #[test]
+    fn realign_closes_the_gap_once_unprimed() {
+        let mut controller = controller();
+        // the first window is always put on the target, however close
+        assert_eq!(controller.realign(TARGET as f64 - 10.0), 10);
+        assert_eq!(controller.state().realigns, 1);
+        assert_eq!(controller.state().filtered_frames, TARGET as f64);
+
+        // resync_secs is 240 frames at 48 kHz
+        assert_eq!(controller.realign(TARGET as f64 + 240.0), 0);
+        assert_eq!(controller.realign(TARGET as f64 - 240.0), 0);
+        assert_eq!(controller.realign(TARGET as f64 + 1000.4), -1000);
+        assert_eq!(controller.state().realigns, 2);
+    }
+
+    #[test]
+    fn realign_keeps_the_integral() {
+        let mut controller = controller();
+        controller.update(TARGET as f64 + frames(0.0005), 1.0);
+        let integral = controller.state().integral;
+        assert!(integral > 0.0);
+        assert_eq!(controller.realign(TARGET as f64 - 2000.0), 2000);
+        assert_eq!(controller.state().integral, integral);
+        assert_eq!(controller.state().realigns, 1);
+    }
+
+    #[test]
+    fn realign_on_target_counts_nothing() {
+        let mut controller = controller();
+        assert_eq!(controller.realign(TARGET as f64), 0);
+        assert_eq!(controller.state().realigns, 0);
+    }
+
+    fn secs(secs: f64) -> Duration {
+        Duration::from_secs_f64(secs)
+    }
+
+    #[test]
+    fn meter_weights_levels_by_how_long_they_held() {
+        let mut meter = FillMeter::new();
+        assert_eq!(meter.take(), None);
+        meter.sample(secs(1.0), 100);
+        meter.sample(secs(1.3), 200);
+        meter.sample(secs(1.4), 0);
+        assert!((meter.take().unwrap() - 125.0).abs() < 1e-9);
+
+        // the last level carries on into the next average
+        meter.sample(secs(2.4), 50);
+        assert!((meter.take().unwrap() - 0.0).abs() < 1e-9);
+        meter.sample(secs(2.4), 60);
+        assert_eq!(meter.take(), None);
+    }
+
+    /// A tracker fed one sink period at a time at a fixed fill level
+    struct Periods {
+        tracker: DriftTracker,
+        at: Duration,
+        read: u64,
+    }
+
+    impl Periods {
+        fn new() -> Self {
+            Self {
+                tracker: DriftTracker::new(DriftConfig::default(), CAPACITY, RATE, PERIOD),
+                at: Duration::ZERO,
+                read: 0,
+            }
+        }
+
+        fn period(&mut self, fill: usize, glitches: u64) -> Option<DriftStep> {
+            self.tracker.sample(self.at, fill);
+            self.at += secs(PERIOD as f64 / RATE as f64);
+            self.tracker.sample(self.at, fill);
+            self.read += PERIOD as u64;
+            self.tracker.step(self.read, glitches)
+        }
+    }
+
+    #[test]
+    fn tracker_waits_for_a_whole_period_after_the_first_read() {
+        let mut periods = Periods::new();
+        assert_eq!(periods.tracker.step(0, 0), None);
+        // the first read opens a window
+        assert_eq!(periods.period(TARGET, 0), None);
+        // a read less than a period later doesn't close it
+        periods.read += PERIOD as u64 - 1;
+        assert_eq!(periods.tracker.step(periods.read, 0), None);
+        periods.read -= PERIOD as u64 - 1;
+
+        let step = periods.period(TARGET - 100, 0).unwrap();
+        assert_eq!(step.gap, 100);
+        assert_eq!(step.ratio, 1.0);
+        let step = periods.period(TARGET + 100, 0).unwrap();
+        assert_eq!(step.gap, 0);
+        assert!(step.ratio < 1.0);
+    }
+
+    #[test]
+    fn tracker_realigns_after_a_glitch() {
+        let mut periods = Periods::new();
+        periods.period(TARGET, 0);
+        assert_eq!(periods.period(TARGET, 0).unwrap().gap, 0);
+
+        // the window with the glitch in it is skipped, the next realigns
+        assert_eq!(periods.period(TARGET - 1000, 1), None);
+        assert_eq!(periods.period(TARGET - 1000, 1).unwrap().gap, 1000);
+        assert_eq!(periods.tracker.state().realigns, 1);
+        // and only once
+        assert_eq!(periods.period(TARGET - 1000, 1).unwrap().gap, 0);
+    }
+
+    #[test]
+    fn tracker_drains_a_surplus_before_measuring_again() {
+        let mut periods = Periods::new();
+        periods.period(TARGET, 0);
+        assert_eq!(periods.period(TARGET + 1000, 0).unwrap().gap, -1000);
+
+        assert_eq!(periods.tracker.skip(600), 600);
+        assert_eq!(periods.period(TARGET + 400, 0), None);
+        assert_eq!(periods.tracker.skip(600), 400);
+        assert_eq!(periods.tracker.skip(600), 0);
+        // the window the surplus ran out in straddles the drain
+        assert_eq!(periods.period(TARGET, 0), None);
+        assert_eq!(periods.period(TARGET, 0).unwrap().gap, 0);
+    }
+
+    /// A bridge whose source runs 150 ppm fast has to settle on the opposite
+    /// correction and then never overflow or run the sink dry
+    #[test]
+    fn bridge_settles_on_a_drifting_source() {
+        let ppm = 150.0;
+        let mut settled: Option<(u64, BridgeStatus)> = None;
+        let mut corrections = Vec::new();
+        run_drifting(
+            ppm,
+            Some(Default::default()),
+            25.0,
+            |played, underruns, status| {
+                if played < 15.0 {
+                    return;
+                }
+                let (was_underruns, start) =
+                    settled.get_or_insert_with(|| (underruns, status.clone()));
+                let drift = status.drift.unwrap();
+                corrections.push(drift.ppm());
+                assert_eq!(underruns, *was_underruns);
+                assert_eq!(drift.realigns, start.drift.unwrap().realigns);
+                let (sink, was) = (status.sink_ring, start.sink_ring);
+                assert_eq!(sink.dropped_frames, was.dropped_frames);
+                assert_eq!(sink.short_reads, was.short_reads);
+                assert_eq!(sink.zero_reads, was.zero_reads);
+                assert_eq!(
+                    status.source_ring.dropped_frames,
+                    start.source_ring.dropped_frames
+                );
+            },
+        );
+
+        let average = corrections.iter().sum::<f64>() / corrections.len() as f64;
+        // a fast source needs a ratio below 1.0 to keep up with the sink
+        assert!(
+            (average + ppm).abs() < 10.0,
+            "correction {average:+.1} ppm for a source {ppm:+.1} ppm off"
+        );
+    }
+}
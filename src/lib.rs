@@ -0,0 +1,28 @@
+//! Bridge audio from one backend to another through lock-free frame rings,
+//! resampling between the two clocks.
+//!
+//! The ASIO and WASAPI backends sit behind the `asio` and `wasapi` features and
+//! only build on Windows; the ring, conversion and resampling stages are
+//! available everywhere.
+
+#[cfg(all(windows, feature = "asio"))]
+pub mod asio;
+pub mod asio_sample;
+pub mod backend;
+pub mod bridge;
+pub mod channel_map;
+pub mod clock;
+pub mod convert;
+pub mod devices;
+pub mod drift;
+pub mod resample;
+pub mod ring;
+pub mod sample;
+pub mod selector;
+pub mod sim;
+pub mod timestamp;
+mod util;
+pub mod visualizer;
+#[cfg(all(windows, feature = "wasapi"))]
+pub mod wasapi;
+pub mod wav;
@@ -1,14 +1,29 @@
-mod asio;
-mod ring;
-mod util;
-mod visualizer;
-mod wasapi;
-
-use std::sync::Arc;
-use visualizer::AudioVisualizer;
+#[cfg(all(windows, feature = "asio", feature = "wasapi"))]
+mod cli;
 
+#[cfg(all(windows, feature = "asio", feature = "wasapi"))]
 fn main() -> anyhow::Result<()> {
-    // needs to be big enough to hold bursty asio buffers
+    use asio_wdm_bridge::{asio, devices::DeviceList, visualizer::AudioVisualizer, wasapi};
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    let args = cli::Args::parse()?;
+
+    if let cli::Command::ListDevices { json } = args.command {
+        let devices = DeviceList {
+            asio: asio::list_drivers(),
+            wasapi: wasapi::list_endpoints()?,
+        };
+        if json {
+            println!("{}", devices.to_json());
+        } else {
+            print!("{}", devices.to_table());
+        }
+        return Ok(());
+    }
+
     let visualizer = Arc::new(AudioVisualizer::new());
 
     // Start the visualizer
@@ -17,16 +32,103 @@ fn main() -> anyhow::Result<()> {
     // Set the visualizer for ASIO
     asio::set_visualizer(visualizer.clone());
 
-    // largest asio buffer
-    let (asio_producer, asio_consumer) = ring::new_framering(2, 2048, "asio");
+    let mut bridge = Some(start_bridge(&args)?);
+    let mut backoff = REOPEN_BACKOFF_MIN;
+    let mut reopen_at = Instant::now();
 
-    unsafe {
-        asio::start_asio(asio_producer)?;
+    loop {
+        std::thread::sleep(Duration::from_millis(100));
+
+        let events = asio::take_driver_events();
+        if events.resyncs > 0 {
+            println!("ASIO driver lost sync {} time(s)", events.resyncs);
+        }
+        if events.latencies_changed {
+            println!("ASIO driver latencies changed");
+        }
+        if let Some(reason) = events.reset {
+            println!("ASIO driver asked for a reset ({:?}), reopening it", reason);
+            // the old bridge has to let go of the driver before it can be opened again
+            drop(bridge.take());
+            backoff = REOPEN_BACKOFF_MIN;
+            reopen_at = Instant::now();
+        }
+
+        // a driver that is still busy or settling on its new rate gets more time
+        if bridge.is_none() && Instant::now() >= reopen_at {
+            match start_bridge(&args) {
+                Ok(reopened) => {
+                    println!("Bridge reopened");
+                    bridge = Some(reopened);
+                }
+                Err(e) => {
+                    println!(
+                        "Failed to reopen the bridge, retrying in {:?}: {:?}",
+                        backoff, e
+                    );
+                    reopen_at = Instant::now() + backoff;
+                    backoff = (backoff * 2).min(REOPEN_BACKOFF_MAX);
+                }
+            }
+        }
     }
+}
 
-    wasapi::start_wasapi(asio_consumer, 192000, 2)?;
+/// First wait before retrying a bridge that failed to reopen after a reset
+#[cfg(all(windows, feature = "asio", feature = "wasapi"))]
+const REOPEN_BACKOFF_MIN: std::time::Duration = std::time::Duration::from_millis(250);
 
-    loop {
-        std::thread::sleep(std::time::Duration::from_secs(1));
+/// Longest wait between reopen attempts
+#[cfg(all(windows, feature = "asio", feature = "wasapi"))]
+const REOPEN_BACKOFF_MAX: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Open both devices and start streaming between them.
+#[cfg(all(windows, feature = "asio", feature = "wasapi"))]
+fn start_bridge(args: &cli::Args) -> anyhow::Result<asio_wdm_bridge::bridge::Bridge> {
+    let mut bridge = open_bridge(args)?;
+    bridge.start()?;
+    Ok(bridge)
+}
+
+/// Open both devices for the direction picked on the command line.
+#[cfg(all(windows, feature = "asio", feature = "wasapi"))]
+fn open_bridge(args: &cli::Args) -> anyhow::Result<asio_wdm_bridge::bridge::Bridge> {
+    use asio_wdm_bridge::{
+        asio,
+        backend::{AudioSink, AudioSource},
+        bridge::Bridge,
+        wasapi,
+    };
+
+    match args.capture {
+        None => {
+            let source =
+                asio::AsioSource::new(args.driver.as_ref(), args.inputs.as_ref(), args.asio_rate)?;
+
+            // matching the ASIO rate leaves the resampler nothing to do
+            let rates = match args.wasapi_rate {
+                Some(rate) => vec![rate],
+                None => std::iter::once(source.sample_rate())
+                    .chain(wasapi::CANDIDATE_RATES)
+                    .collect(),
+            };
+            let sink = wasapi::WasapiSink::new(&rates, source.channels())?;
+
+            Bridge::builder().source(source).sink(sink).build()
+        }
+        Some(mode) => {
+            let sink =
+                asio::AsioSink::new(args.driver.as_ref(), args.outputs.as_ref(), args.asio_rate)?;
+            let source = wasapi::WasapiSource::new(mode, args.wasapi_rate, sink.channels())?;
+
+            Bridge::builder().source(source).sink(sink).build()
+        }
     }
 }
+
+#[cfg(not(all(windows, feature = "asio", feature = "wasapi")))]
+fn main() -> anyhow::Result<()> {
+    anyhow::bail!(
+        "built without the ASIO and WASAPI backends; they need Windows and the `asio` and `wasapi` features"
+    )
+}
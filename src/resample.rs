@@ -0,0 +1,277 @@
+use anyhow::Result;
+use audioadapter_buffers::direct::InterleavedSlice;
+use rubato::{Async, FixedAsync, PolynomialDegree, Resampler, SincInterpolationParameters};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, yield_now, JoinHandle},
+    time::Instant,
+};
+
+use crate::clock::{Timeline, TimelineMember};
+use crate::drift::{DriftConfig, DriftState, DriftTracker};
+use crate::ring::{FrameRingConsumer, FrameRingProducer};
+use crate::timestamp::{ClockEstimate, ClockEstimator, TimestampConsumer};
+use crate::util::*;
+
+/// What the resampler thread converts between.
+#[derive(Clone, Copy, Debug)]
+pub struct ResamplerConfig {
+    pub input_rate: usize,
+    pub output_rate: usize,
+    pub channels: usize,
+    /// Frames the sink takes per period, the drift controller acts on the fill
+    /// averaged from one sink read to the next at least as many frames later
+    pub sink_period: usize,
+    /// Steer the ratio to hold the sink ring level, `None` keeps it fixed
+    pub drift: Option<DriftConfig>,
+}
+
+/// How the resampler thread gets frames from one ring to the other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResampleMode {
+    /// Rates match and the ratio is fixed, frames are copied straight through
+    Passthrough,
+    /// Rates match but drift compensation needs a variable ratio, a cubic
+    /// polynomial is enough for corrections of a few hundred ppm
+    ShortFilter,
+    /// Full sinc interpolation between different rates
+    Sinc,
+}
+
+impl ResampleMode {
+    pub fn select(config: &ResamplerConfig) -> Self {
+        match (config.input_rate == config.output_rate, config.drift) {
+            (true, None) => ResampleMode::Passthrough,
+            (true, Some(_)) => ResampleMode::ShortFilter,
+            (false, _) => ResampleMode::Sinc,
+        }
+    }
+}
+
+/// System time the source clock estimate is fitted over
+const CLOCK_WINDOW_SECS: f64 = 10.0;
+
+/// Spawn the thread that moves frames from the source ring to the sink ring,
+/// converting from `input_rate` to `output_rate` on the way.
+///
+/// With drift compensation enabled the controller state is published to
+/// `drift_state` after every chunk. Source `timestamps`, if there are any, are
+/// fitted against the system clock and published to `source_clock`. On the
+/// sink's `timeline`, if it has one, the fill level is timed by it and the
+/// thread waits its turn for input. The thread exits once `running` is cleared.
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_resampler(
+    mut input: FrameRingConsumer,
+    mut output: FrameRingProducer,
+    config: ResamplerConfig,
+    running: Arc<AtomicBool>,
+    drift_state: Arc<Mutex<DriftState>>,
+    mut timestamps: Option<TimestampConsumer>,
+    source_clock: Arc<Mutex<Option<ClockEstimate>>>,
+    timeline: Option<Timeline>,
+) -> Result<JoinHandle<()>> {
+    let ResamplerConfig {
+        input_rate,
+        output_rate,
+        channels,
+        sink_period,
+        drift,
+    } = config;
+    let resample_ratio = output_rate as f64 / input_rate as f64;
+    let resample_chunk_size = 64;
+    let mode = ResampleMode::select(&config);
+    let mut resampler = match mode {
+        ResampleMode::Passthrough => None,
+        ResampleMode::ShortFilter => Some(Async::<f32>::new_poly(
+            resample_ratio,
+            1.1, // Max ratio relative
+            PolynomialDegree::Cubic,
+            resample_chunk_size,
+            channels,
+            FixedAsync::Output,
+        )?),
+        ResampleMode::Sinc => Some(Async::<f32>::new_sinc(
+            resample_ratio,
+            1.1, // Max ratio relative
+            &SincInterpolationParameters {
+                sinc_len: 64,
+                f_cutoff: 0.95,
+                oversampling_factor: 128,
+                interpolation: rubato::SincInterpolationType::Cubic,
+                window: rubato::WindowFunction::BlackmanHarris2,
+            },
+            resample_chunk_size, // Chunk size
+            channels,            // Number of channels
+            FixedAsync::Output,  // Fixed input size
+        )?),
+    };
+    info!(
+        "Resampler mode: {:?} ({} Hz -> {} Hz)",
+        mode, input_rate, output_rate
+    );
+
+    // preallocated buffer, we need no more than the largest resampler input
+    let staging_frames = resampler
+        .as_ref()
+        .map_or(resample_chunk_size, |r| r.input_frames_max());
+    let mut staging = vec![0.0f32; staging_frames * channels];
+
+    let mut tracker =
+        drift.map(|drift| DriftTracker::new(drift, output.capacity(), output_rate, sink_period));
+    // frames on their way to the sink, in output frames; counting the source
+    // ring too hides the steps its periods leave in the sink ring
+    let buffered = move |input: &FrameRingConsumer, output: &FrameRingProducer| {
+        output.usage() + input.available_frames() * output_rate / input_rate
+    };
+    let sink_stats = output.stats();
+    // underruns and overflows on either ring
+    let glitches = |input: &FrameRingConsumer, output: &FrameRingProducer| {
+        let (source, sink) = (input.stats().snapshot(), output.stats().snapshot());
+        source.dropped_frames + sink.dropped_frames + sink.short_reads + sink.zero_reads
+    };
+    let mut estimator = ClockEstimator::new(input_rate, CLOCK_WINDOW_SECS);
+    // joined here so the timeline can't move on before the thread is up
+    let member = timeline.map(|timeline| timeline.join());
+
+    let handle = thread::Builder::new()
+        .name("resampler".into())
+        .spawn(move || {
+            let started = Instant::now();
+            let now = || member.as_ref().map_or_else(|| started.elapsed(), |m| m.now());
+            let mut last_log = Instant::now();
+            while running.load(Ordering::Relaxed) {
+                let epoch = member.as_ref().map_or(0, |member| member.epoch());
+                if let Some(timestamps) = &mut timestamps {
+                    while let Some(timestamp) = timestamps.pop() {
+                        estimator.update(&timestamp);
+                    }
+                }
+
+                if last_log.elapsed().as_millis() >= 1000 {
+                    if timestamps.is_some() {
+                        let estimate = estimator.estimate();
+                        if let Some(clock) = estimate {
+                            info!(
+                                "Source clock: {:.3} Hz ({:+.1} ppm), jitter {:.0} us over {:.1} s",
+                                clock.rate,
+                                clock.ppm,
+                                clock.jitter_secs * 1e6,
+                                clock.span_secs
+                            );
+                        }
+                        *source_clock.lock().unwrap() = estimate;
+                    }
+                    let (source, sink) = (input.stats().snapshot(), output.stats().snapshot());
+                    info!(
+                        "Source ring: {} frames available, {} dropped; sink ring: {} frames used, {} short and {} empty reads, {} frames padded",
+                        input.available_frames(),
+                        source.dropped_frames,
+                        output.usage(),
+                        sink.short_reads,
+                        sink.zero_reads,
+                        sink.padded_frames
+                    );
+                    if let Some(tracker) = &tracker {
+                        let state = tracker.state();
+                        info!(
+                            "Drift: target {} frames, filtered {:.1}, correction {:+.1} ppm",
+                            state.target_frames,
+                            state.filtered_frames,
+                            state.ppm()
+                        );
+                    }
+                    last_log = Instant::now();
+                }
+
+                let Some(resampler) = &mut resampler else {
+                    // copy whatever is there, up to a chunk at a time
+                    let frames = input.available_frames().min(staging_frames);
+                    let staging = &mut staging[..frames * channels];
+                    if frames == 0 || input.pop_into(frames, staging) == 0 {
+                        wait_for_input(member.as_ref(), epoch);
+                        continue;
+                    }
+                    output.push(staging);
+                    continue;
+                };
+
+                if let Some(tracker) = &mut tracker {
+                    tracker.sample(now(), buffered(&input, &output));
+                    let read = sink_stats.popped_frames.load(Ordering::Relaxed);
+                    if let Some(step) = tracker.step(read, glitches(&input, &output)) {
+                        if step.gap > 0 {
+                            output.push(&vec![0.0; step.gap as usize * channels]);
+                            tracker.sample(now(), buffered(&input, &output));
+                        }
+                        if step.gap != 0 {
+                            info!("Drift: sink ring realigned by {:+} frames", step.gap);
+                        }
+                        if let Err(e) = resampler.set_resample_ratio_relative(step.ratio, true) {
+                            warn!("Failed to adjust resampler ratio: {:?}", e);
+                        }
+                        if let Ok(mut state) = drift_state.try_lock() {
+                            *state = tracker.state();
+                        }
+                    }
+                }
+
+                // Accumulate input samples
+                let in_frames = resampler.input_frames_next();
+                let in_samples = in_frames * channels;
+
+                // only a read that can succeed counts against the ring's stats
+                let staging = &mut staging[..in_samples];
+                if input.available_frames() < in_frames || input.pop_into(in_frames, staging) == 0
+                {
+                    wait_for_input(member.as_ref(), epoch);
+                    continue;
+                }
+
+                // Prepare input slice for resampler
+                let adapter = match InterleavedSlice::new(staging, channels, in_frames) {
+                    Ok(i) => i,
+                    Err(e) => {
+                        warn!("Failed to create input slice: {:?}", e);
+                        continue;
+                    }
+                };
+
+                // Resample
+                let resampled = match resampler.process(&adapter, 0, None) {
+                    Ok(o) => o.take_data(),
+                    Err(e) => {
+                        warn!("Resampler error: {:?}", e);
+                        continue;
+                    }
+                };
+
+                let skipped = tracker
+                    .as_mut()
+                    .map_or(0, |tracker| tracker.skip(resampled.len() / channels));
+                let resampled = &resampled[skipped * channels..];
+
+                // Push resampled frames into rtrb ring (fast bulk operation!)
+                if !resampled.is_empty() {
+                    output.push(resampled);
+                }
+
+                if let Some(tracker) = &mut tracker {
+                    tracker.sample(now(), buffered(&input, &output));
+                }
+            }
+        })?;
+
+    Ok(handle)
+}
+
+/// Let the source catch up: on a timeline until another member has had a turn
+/// since `epoch`, otherwise by giving up the CPU
+fn wait_for_input(member: Option<&TimelineMember>, epoch: u64) {
+    match member {
+        Some(member) => member.idle(epoch),
+        None => yield_now(),
+    }
+}
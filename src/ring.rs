@@ -1,104 +1,1031 @@
-use rtrb::{chunks::ChunkError, Consumer, Producer, RingBuffer};
+use rtrb::{
+    chunks::{ReadChunk, WriteChunk},
+    Consumer, Producer, RingBuffer,
+};
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 use util::*;
 
+use crate::sample::{convert_slice, Sample};
 use crate::util;
 /// Fast lock-free ring buffer using rtrb
-pub struct FrameRingConsumer {
+///
+/// Frames are interleaved samples of type `S`; the f32 default is what the
+/// resampler and backends work in.
+pub struct FrameRingConsumer<S: Sample = f32> {
     channels: usize,
-    consumer: Consumer<f32>,
+    consumer: Consumer<S>,
     name: String,
+    stats: Arc<RingStats>,
+    /// Frames the producer holds back for want of room, see [`OverflowPolicy::DropOldest`]
+    held_back: Arc<AtomicUsize>,
 }
 
-pub struct FrameRingProducer {
+pub struct FrameRingProducer<S: Sample = f32> {
     channels: usize,
-    producer: Producer<f32>,
+    producer: Producer<S>,
     name: String,
+    stats: Arc<RingStats>,
+    policy: OverflowPolicy,
+    /// Newest frames waiting for the consumer to skip the oldest, `DropOldest` only
+    pending: Vec<S>,
+    /// `pending` in frames, published for the consumer
+    held_back: Arc<AtomicUsize>,
+    /// Where a `DropOldest` write chunk goes when the ring can't take it
+    staging: Vec<S>,
 }
 
-pub fn new_framering(
+/// What [`FrameRingProducer::push`] does with frames that don't fit.
+///
+/// Every policy drops whole frames only.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Keep what is queued and lose the new frames; latency stays at a full ring
+    #[default]
+    DropNewest,
+    /// Keep the new frames and have the consumer skip the oldest ones instead,
+    /// so what plays is as recent as the ring allows
+    DropOldest,
+    /// Wait up to the timeout for the consumer to make room, then drop the
+    /// newest. For offline use, never from a real-time callback
+    Block(Duration),
+}
+
+/// Counters kept by both ends of a frame ring, readable from any thread while it runs.
+///
+/// The producer side shows what the writer, e.g. an ASIO callback, lost; the
+/// consumer side what the reader, e.g. a WASAPI render loop, had to make up.
+#[derive(Debug)]
+pub struct RingStats {
+    /// Frames pushed into the ring
+    pub pushed_frames: AtomicU64,
+    /// Frames thrown away because the ring was full, the newest or with
+    /// [`OverflowPolicy::DropOldest`] the oldest
+    pub dropped_frames: AtomicU64,
+    /// Frames read out of the ring
+    pub popped_frames: AtomicU64,
+    /// Reads that found some frames, but fewer than asked for
+    pub short_reads: AtomicU64,
+    /// Reads that found the ring empty
+    pub zero_reads: AtomicU64,
+    /// Silent frames filled in for missing ones, by [`FrameRingConsumer::pop_padded_into`]
+    /// or a reader padding out a short [`FrameReadChunk`]
+    pub padded_frames: AtomicU64,
+    /// Fill level in frames right after each push, `usize::MAX` low until the first
+    pub producer_high: AtomicUsize,
+    pub producer_low: AtomicUsize,
+    /// Fill level in frames right before each read, `usize::MAX` low until the first
+    pub consumer_high: AtomicUsize,
+    pub consumer_low: AtomicUsize,
+}
+
+/// Lowest and highest fill level one end of a ring has seen, in frames.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Watermarks {
+    pub low: usize,
+    pub high: usize,
+}
+
+/// [`RingStats`] read at one point in time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RingStatsSnapshot {
+    pub pushed_frames: u64,
+    pub dropped_frames: u64,
+    pub popped_frames: u64,
+    pub short_reads: u64,
+    pub zero_reads: u64,
+    pub padded_frames: u64,
+    /// Both zero until the first push
+    pub producer: Watermarks,
+    /// Both zero until the first read
+    pub consumer: Watermarks,
+}
+
+impl Default for RingStats {
+    fn default() -> Self {
+        Self {
+            pushed_frames: AtomicU64::new(0),
+            dropped_frames: AtomicU64::new(0),
+            popped_frames: AtomicU64::new(0),
+            short_reads: AtomicU64::new(0),
+            zero_reads: AtomicU64::new(0),
+            padded_frames: AtomicU64::new(0),
+            producer_high: AtomicUsize::new(0),
+            producer_low: AtomicUsize::new(usize::MAX),
+            consumer_high: AtomicUsize::new(0),
+            consumer_low: AtomicUsize::new(usize::MAX),
+        }
+    }
+}
+
+impl RingStats {
+    pub fn snapshot(&self) -> RingStatsSnapshot {
+        let watermarks = |low: &AtomicUsize, high: &AtomicUsize| match low.load(Ordering::Relaxed) {
+            usize::MAX => Watermarks::default(),
+            low => Watermarks {
+                low,
+                high: high.load(Ordering::Relaxed),
+            },
+        };
+        RingStatsSnapshot {
+            pushed_frames: self.pushed_frames.load(Ordering::Relaxed),
+            dropped_frames: self.dropped_frames.load(Ordering::Relaxed),
+            popped_frames: self.popped_frames.load(Ordering::Relaxed),
+            short_reads: self.short_reads.load(Ordering::Relaxed),
+            zero_reads: self.zero_reads.load(Ordering::Relaxed),
+            padded_frames: self.padded_frames.load(Ordering::Relaxed),
+            producer: watermarks(&self.producer_low, &self.producer_high),
+            consumer: watermarks(&self.consumer_low, &self.consumer_high),
+        }
+    }
+}
+
+pub fn new_framering<S: Sample>(
     channels: usize,
     capacity: usize,
     name: &str,
-) -> (FrameRingProducer, FrameRingConsumer) {
+) -> (FrameRingProducer<S>, FrameRingConsumer<S>) {
     info!(
         "Creating {} ring buffer with capacity {} frames",
         name, capacity
     );
-    let (producer, consumer) = RingBuffer::<f32>::new(capacity * channels);
+    let (producer, consumer) = RingBuffer::<S>::new(capacity * channels);
+    let stats = Arc::new(RingStats::default());
+    let held_back = Arc::new(AtomicUsize::new(0));
     (
-        FrameRingProducer::new(channels, producer, name),
-        FrameRingConsumer::new(channels, consumer, name),
+        FrameRingProducer::new(channels, producer, name, stats.clone(), held_back.clone()),
+        FrameRingConsumer::new(channels, consumer, name, stats, held_back),
     )
 }
 
-impl FrameRingProducer {
-    fn new(channels: usize, producer: Producer<f32>, name: &str) -> Self {
+impl<S: Sample> FrameRingProducer<S> {
+    fn new(
+        channels: usize,
+        producer: Producer<S>,
+        name: &str,
+        stats: Arc<RingStats>,
+        held_back: Arc<AtomicUsize>,
+    ) -> Self {
         Self {
             channels,
             producer,
             name: name.to_owned(),
+            stats,
+            policy: OverflowPolicy::default(),
+            pending: Vec::new(),
+            held_back,
+            staging: Vec::new(),
         }
     }
 
+    /// What `push` does with frames that don't fit, [`OverflowPolicy::DropNewest`] by default
+    pub fn with_overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.policy = policy;
+        // allocated here so a push or write chunk never has to
+        let samples = self.capacity() * self.channels;
+        (self.pending, self.staging) = match policy {
+            OverflowPolicy::DropOldest => {
+                (Vec::with_capacity(samples), vec![S::default(); samples])
+            }
+            _ => (Vec::new(), Vec::new()),
+        };
+        self
+    }
+
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Counters shared with the consumer end
+    pub fn stats(&self) -> Arc<RingStats> {
+        self.stats.clone()
+    }
+
     pub fn available_frames(&self) -> usize {
         self.producer.slots() / self.channels
     }
 
+    pub fn capacity(&self) -> usize {
+        self.producer.buffer().capacity() / self.channels
+    }
+
     pub fn usage(&self) -> usize {
         self.producer.buffer().capacity() / self.channels - self.producer.slots() / self.channels
     }
 
-    pub fn push(&mut self, output: &[f32]) {
-        let mut chunk = {
-            let chunk = self.producer.write_chunk(output.len());
-            if let Ok(chunk) = chunk {
-                chunk
-            } else {
-                // write what we can
-                let ChunkError::TooFewSlots(available) = chunk.unwrap_err();
-                self.producer.write_chunk(available).unwrap()
+    /// Queue interleaved frames, handling what doesn't fit as the overflow policy says.
+    ///
+    /// Only whole frames are ever written; a trailing partial frame in `output`
+    /// is ignored, so the channels can't shift against each other.
+    pub fn push(&mut self, output: &[S]) {
+        self.push_converted(output);
+    }
+
+    /// Like `push`, converting from another sample type on the way in
+    pub fn push_converted<T: Sample>(&mut self, output: &[T]) {
+        let frames = output.len() / self.channels;
+        let output = &output[..frames * self.channels];
+
+        let dropped = match self.policy {
+            OverflowPolicy::DropNewest => frames - self.write_frames(output),
+            OverflowPolicy::DropOldest => self.push_dropping_oldest(output),
+            OverflowPolicy::Block(timeout) => {
+                let deadline = Instant::now() + timeout;
+                let mut rest = output;
+                loop {
+                    let written = self.write_frames(rest);
+                    rest = &rest[written * self.channels..];
+                    if rest.is_empty() || Instant::now() >= deadline {
+                        break;
+                    }
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                rest.len() / self.channels
             }
         };
 
-        let (a, b) = chunk.as_mut_slices();
+        if dropped > 0 {
+            self.stats
+                .dropped_frames
+                .fetch_add(dropped as u64, Ordering::Relaxed);
+        }
+        self.record_fill();
+    }
+
+    /// Update the producer watermarks after a write
+    fn record_fill(&self) {
+        let fill = self.usage();
+        self.stats.producer_high.fetch_max(fill, Ordering::Relaxed);
+        self.stats.producer_low.fetch_min(fill, Ordering::Relaxed);
+    }
+
+    /// Write frames held back by earlier pushes, then `output`.
+    ///
+    /// The producer can't free slots itself, so whatever doesn't fit waits in
+    /// `pending` and the consumer skips as many of the oldest frames as it
+    /// takes to make room for it. Returns the frames dropped here because even
+    /// `pending` is full.
+    fn push_dropping_oldest<T: Sample>(&mut self, output: &[T]) -> usize {
+        let written = if self.flush_pending() {
+            self.write_frames(output)
+        } else {
+            0
+        };
+        let rest = &output[written * self.channels..];
+        let mut pending = std::mem::take(&mut self.pending);
+
+        // more than a ring full can never be played, the oldest of it goes now
+        let capacity = self.capacity() * self.channels;
+        let excess = (pending.len() + rest.len()).saturating_sub(capacity);
+        let from_pending = excess.min(pending.len());
+        pending.drain(..from_pending);
+        pending.extend(
+            rest[excess - from_pending..]
+                .iter()
+                .map(|&sample| sample.convert::<S>()),
+        );
+
+        self.held_back
+            .store(pending.len() / self.channels, Ordering::Release);
+        self.pending = pending;
+        excess / self.channels
+    }
+
+    /// Write as many of the frames held back in `pending` as fit, returning
+    /// true once none are left
+    fn flush_pending(&mut self) -> bool {
+        if self.pending.is_empty() {
+            return true;
+        }
+        let held_back = self.pending.len() / self.channels;
+        let frames = held_back.min(self.available_frames());
+        // published before the write, so a consumer that sees the written
+        // frames never counts them as held back too
+        self.held_back.store(held_back - frames, Ordering::Release);
+        let mut pending = std::mem::take(&mut self.pending);
+        self.write_frames(&pending[..frames * self.channels]);
+        pending.drain(..frames * self.channels);
+        self.pending = pending;
+        self.pending.is_empty()
+    }
+
+    /// Hand out ring memory for up to `frames` whole frames, to be written in
+    /// place and committed.
+    ///
+    /// Saves the copy `push` makes when the samples have to be produced
+    /// anyway, e.g. decoded from a device buffer. Frames that don't fit are
+    /// handled as the overflow policy says: with [`OverflowPolicy::DropOldest`]
+    /// the chunk is staged and pushed on commit instead, and the copy is only
+    /// made while the ring overflows.
+    pub fn write_chunk(&mut self, frames: usize) -> FrameWriteChunk<'_, S> {
+        if let OverflowPolicy::Block(timeout) = self.policy {
+            let deadline = Instant::now() + timeout;
+            while self.available_frames() < frames && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
 
-        a.copy_from_slice(&output[..a.len()]);
-        b.copy_from_slice(&output[a.len()..(a.len() + b.len())]);
+        let flushed = self.flush_pending();
+        let room = self.available_frames();
+        let staged = self.policy == OverflowPolicy::DropOldest && (!flushed || room < frames);
+        let granted = if staged {
+            // more than a ring full can never be played
+            frames.min(self.capacity())
+        } else {
+            frames.min(room)
+        };
+        if granted < frames {
+            self.stats
+                .dropped_frames
+                .fetch_add((frames - granted) as u64, Ordering::Relaxed);
+        }
+
+        let channels = self.channels;
+        if staged {
+            return FrameWriteChunk {
+                target: ChunkTarget::Staged {
+                    producer: self,
+                    frames: granted,
+                },
+                channels,
+            };
+        }
+        let fill = self.usage();
+        FrameWriteChunk {
+            target: ChunkTarget::Ring {
+                chunk: self
+                    .producer
+                    .write_chunk(granted * channels)
+                    .expect("only the producer takes slots"),
+                stats: &self.stats,
+                fill,
+            },
+            channels,
+        }
+    }
+
+    /// Write as many whole frames of `samples` as fit, returning how many did
+    fn write_frames<T: Sample>(&mut self, samples: &[T]) -> usize {
+        let frames = (samples.len() / self.channels).min(self.available_frames());
+        if frames == 0 {
+            return 0;
+        }
+        let Ok(mut chunk) = self.producer.write_chunk(frames * self.channels) else {
+            return 0;
+        };
+        let (a, b) = chunk.as_mut_slices();
+        convert_slice(&samples[..a.len()], a);
+        convert_slice(&samples[a.len()..a.len() + b.len()], b);
         chunk.commit_all();
+        self.stats
+            .pushed_frames
+            .fetch_add(frames as u64, Ordering::Relaxed);
+        frames
     }
 }
 
-impl FrameRingConsumer {
-    fn new(channels: usize, consumer: Consumer<f32>, name: &str) -> Self {
+impl<S: Sample> FrameRingConsumer<S> {
+    fn new(
+        channels: usize,
+        consumer: Consumer<S>,
+        name: &str,
+        stats: Arc<RingStats>,
+        held_back: Arc<AtomicUsize>,
+    ) -> Self {
         Self {
             channels,
             consumer,
             name: name.to_owned(),
+            stats,
+            held_back,
         }
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Counters shared with the producer end
+    pub fn stats(&self) -> Arc<RingStats> {
+        self.stats.clone()
+    }
+
+    /// Frames a read can get, not counting those about to be skipped
     pub fn available_frames(&self) -> usize {
-        self.consumer.slots() / self.channels
+        let filled = self.consumer.slots() / self.channels;
+        filled - self.frames_to_skip(filled)
+    }
+
+    /// Oldest of the `filled` frames to throw away so the frames the producer
+    /// holds back fit behind the rest.
+    ///
+    /// Worked out from the fill level at every read rather than requested
+    /// once, so frames already freed are never skipped for the same held back
+    /// frames twice. `filled` has to be read first: the producer lowers the
+    /// held back count before writing, so a newer count with an older fill
+    /// only skips too few.
+    fn frames_to_skip(&self, filled: usize) -> usize {
+        let capacity = self.consumer.buffer().capacity() / self.channels;
+        let held_back = self.held_back.load(Ordering::Acquire);
+        (filled + held_back).saturating_sub(capacity)
+    }
+
+    /// Read exactly `frames` frames into `out`, or nothing if the ring holds fewer.
+    ///
+    /// Returns the frames read, `frames` or 0.
+    pub fn pop_into(&mut self, frames: usize, out: &mut [S]) -> usize {
+        if out.len() < frames * self.channels {
+            return 0;
+        }
+        if self.record_read(frames) < frames {
+            return 0;
+        }
+        self.read_frames(frames, out)
+    }
+
+    /// Read up to `frames` frames into `out`, as many whole frames as the ring holds.
+    ///
+    /// Returns the frames read.
+    pub fn pop_partial_into(&mut self, frames: usize, out: &mut [S]) -> usize {
+        self.pop_converted_into(frames, out)
+    }
+
+    /// Like `pop_partial_into`, converting to another sample type on the way out
+    pub fn pop_converted_into<T: Sample>(&mut self, frames: usize, out: &mut [T]) -> usize {
+        let frames = frames.min(out.len() / self.channels);
+        let available = self.record_read(frames);
+        self.read_frames(frames.min(available), out)
+    }
+
+    /// Fill `frames` frames of `out` from the ring, padding whatever it is
+    /// short of with silence at the end.
+    ///
+    /// Returns the frames padded, 0 if the ring held enough.
+    pub fn pop_padded_into(&mut self, frames: usize, out: &mut [S]) -> usize {
+        let frames = frames.min(out.len() / self.channels);
+        let read = self.pop_partial_into(frames, out);
+        let padded = frames - read;
+        if padded > 0 {
+            out[read * self.channels..frames * self.channels].fill(S::default());
+            self.stats
+                .padded_frames
+                .fetch_add(padded as u64, Ordering::Relaxed);
+        }
+        padded
+    }
+
+    /// Borrow up to `frames` whole frames straight from ring memory, to be
+    /// read in place and committed.
+    ///
+    /// Saves the copy `pop_partial_into` makes when the frames are converted
+    /// anyway, e.g. into a device format. The chunk holds as many frames as
+    /// the ring does, up to `frames`; only the committed ones leave the ring.
+    pub fn read_chunk(&mut self, frames: usize) -> FrameReadChunk<'_, S> {
+        let granted = self.record_read(frames).min(frames);
+        FrameReadChunk {
+            chunk: self
+                .consumer
+                .read_chunk(granted * self.channels)
+                .expect("only the consumer frees slots"),
+            channels: self.channels,
+            stats: &self.stats,
+        }
+    }
+
+    /// Throw away the oldest frames the producer needs room for
+    fn skip_oldest(&mut self) {
+        let frames = self.frames_to_skip(self.consumer.slots() / self.channels);
+        if frames == 0 {
+            return;
+        }
+        if let Ok(chunk) = self.consumer.read_chunk(frames * self.channels) {
+            chunk.commit_all();
+            self.stats
+                .dropped_frames
+                .fetch_add(frames as u64, Ordering::Relaxed);
+        }
     }
 
-    pub fn pop_into(&mut self, frames: usize, out: &mut [f32]) -> usize {
-        let samples_needed = frames * self.channels;
+    /// Account for a read of `frames` and return how many the ring holds
+    fn record_read(&mut self, frames: usize) -> usize {
+        self.skip_oldest();
+        let stats = &self.stats;
+        let available = self.available_frames();
+        stats.consumer_high.fetch_max(available, Ordering::Relaxed);
+        stats.consumer_low.fetch_min(available, Ordering::Relaxed);
+        if available == 0 && frames > 0 {
+            stats.zero_reads.fetch_add(1, Ordering::Relaxed);
+        } else if available < frames {
+            stats.short_reads.fetch_add(1, Ordering::Relaxed);
+        }
+        available
+    }
 
-        if out.len() < samples_needed {
+    /// Copy `frames` frames the ring is known to hold into the start of `out`
+    fn read_frames<T: Sample>(&mut self, frames: usize, out: &mut [T]) -> usize {
+        if frames == 0 {
+            return 0;
+        }
+        let Ok(chunk) = self.consumer.read_chunk(frames * self.channels) else {
             return 0;
+        };
+        let (a, b) = chunk.as_slices();
+        convert_slice(a, out);
+        convert_slice(b, &mut out[a.len()..]);
+        chunk.commit_all();
+        self.stats
+            .popped_frames
+            .fetch_add(frames as u64, Ordering::Relaxed);
+        frames
+    }
+}
+
+/// Ring memory for whole frames, from [`FrameRingProducer::write_chunk`].
+///
+/// The samples may be left over from earlier passes around the ring, so every
+/// frame that gets committed has to be written in full. Dropping the chunk
+/// without committing writes nothing.
+pub struct FrameWriteChunk<'a, S: Sample> {
+    target: ChunkTarget<'a, S>,
+    channels: usize,
+}
+
+enum ChunkTarget<'a, S: Sample> {
+    /// Straight into the ring, `fill` is the producer fill level when the
+    /// chunk was taken
+    Ring {
+        chunk: WriteChunk<'a, S>,
+        stats: &'a RingStats,
+        fill: usize,
+    },
+    /// Into the producer's staging buffer, pushed on commit so
+    /// [`OverflowPolicy::DropOldest`] can make room for it
+    Staged {
+        producer: &'a mut FrameRingProducer<S>,
+        frames: usize,
+    },
+}
+
+impl<S: Sample> FrameWriteChunk<'_, S> {
+    /// Frames in the chunk
+    pub fn len(&self) -> usize {
+        match &self.target {
+            ChunkTarget::Ring { chunk, .. } => chunk.len() / self.channels,
+            ChunkTarget::Staged { frames, .. } => *frames,
         }
+    }
 
-        // Try to read exact amount
-        match self.consumer.read_chunk(samples_needed) {
-            Ok(chunk) => {
-                let samples_read = chunk.len();
-                let (a, b) = chunk.as_slices();
-                out[..a.len()].copy_from_slice(a);
-                out[a.len()..].copy_from_slice(b);
-                chunk.commit_all();
-                samples_read / self.channels
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The chunk's interleaved samples, split where the ring wraps around.
+    ///
+    /// The ring only ever moves by whole frames, so both slices hold whole
+    /// frames and the second is empty unless the chunk wraps.
+    pub fn as_mut_slices(&mut self) -> (&mut [S], &mut [S]) {
+        match &mut self.target {
+            ChunkTarget::Ring { chunk, .. } => chunk.as_mut_slices(),
+            ChunkTarget::Staged { producer, frames } => {
+                (&mut producer.staging[..*frames * self.channels], &mut [])
             }
-            Err(_) => 0,
         }
     }
+
+    /// The chunk's frames in order, one slice of `channels` samples each
+    pub fn frames_mut(&mut self) -> impl Iterator<Item = &mut [S]> {
+        let channels = self.channels;
+        let (first, second) = self.as_mut_slices();
+        first
+            .chunks_exact_mut(channels)
+            .chain(second.chunks_exact_mut(channels))
+    }
+
+    /// Make the first `frames` frames readable, at most the whole chunk
+    pub fn commit(self, frames: usize) {
+        let frames = frames.min(self.len());
+        match self.target {
+            ChunkTarget::Ring { chunk, stats, fill } => {
+                chunk.commit(frames * self.channels);
+                stats
+                    .pushed_frames
+                    .fetch_add(frames as u64, Ordering::Relaxed);
+                // the consumer may have read since, so this is the most it can be
+                let fill = fill + frames;
+                stats.producer_high.fetch_max(fill, Ordering::Relaxed);
+                stats.producer_low.fetch_min(fill, Ordering::Relaxed);
+            }
+            ChunkTarget::Staged { producer, .. } => {
+                let staging = std::mem::take(&mut producer.staging);
+                producer.push(&staging[..frames * self.channels]);
+                producer.staging = staging;
+            }
+        }
+    }
+
+    pub fn commit_all(self) {
+        let frames = self.len();
+        self.commit(frames);
+    }
+}
+
+/// Frames borrowed from ring memory, from [`FrameRingConsumer::read_chunk`].
+///
+/// Dropping the chunk without committing leaves the frames in the ring.
+pub struct FrameReadChunk<'a, S: Sample> {
+    chunk: ReadChunk<'a, S>,
+    channels: usize,
+    stats: &'a RingStats,
+}
+
+impl<S: Sample> FrameReadChunk<'_, S> {
+    /// Frames in the chunk
+    pub fn len(&self) -> usize {
+        self.chunk.len() / self.channels
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunk.is_empty()
+    }
+
+    /// The chunk's interleaved samples, split where the ring wraps around.
+    ///
+    /// Both slices hold whole frames and the second is empty unless the chunk
+    /// wraps.
+    pub fn as_slices(&self) -> (&[S], &[S]) {
+        self.chunk.as_slices()
+    }
+
+    /// The chunk's frames in order, one slice of `channels` samples each
+    pub fn frames(&self) -> impl Iterator<Item = &[S]> {
+        let (first, second) = self.chunk.as_slices();
+        first
+            .chunks_exact(self.channels)
+            .chain(second.chunks_exact(self.channels))
+    }
+
+    /// Remove the first `frames` frames from the ring, at most the whole chunk
+    pub fn commit(self, frames: usize) {
+        let frames = frames.min(self.len());
+        self.chunk.commit(frames * self.channels);
+        self.stats
+            .popped_frames
+            .fetch_add(frames as u64, Ordering::Relaxed);
+    }
+
+    pub fn commit_all(self) {
+        let frames = self.len();
+        self.commit(frames);
+    }
+}
+
+/// Move up to `max_frames` frames from one ring into another of a different
+/// sample type, converting on the way.
+///
+/// Moves no more than `to` has room for, so nothing is dropped. Both rings
+/// must have the same channel count. Returns the frames moved.
+pub fn transfer<A: Sample, B: Sample>(
+    from: &mut FrameRingConsumer<A>,
+    to: &mut FrameRingProducer<B>,
+    max_frames: usize,
+) -> usize {
+    assert_eq!(from.channels, to.channels, "rings differ in channel count");
+    let frames = from.record_read(max_frames).min(max_frames);
+    let frames = frames.min(to.available_frames());
+    if frames == 0 {
+        return 0;
+    }
+    let samples = frames * from.channels;
+    let (Ok(read), Ok(mut write)) = (
+        from.consumer.read_chunk(samples),
+        to.producer.write_chunk(samples),
+    ) else {
+        return 0;
+    };
+
+    let (a, b) = read.as_slices();
+    let (c, d) = write.as_mut_slices();
+    for (out, &sample) in c.iter_mut().chain(d).zip(a.iter().chain(b)) {
+        *out = sample.convert();
+    }
+    read.commit_all();
+    write.commit_all();
+
+    from.stats
+        .popped_frames
+        .fetch_add(frames as u64, Ordering::Relaxed);
+    to.stats
+        .pushed_frames
+        .fetch_add(frames as u64, Ordering::Relaxed);
+    to.record_fill();
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    /// Stereo frames whose right sample is the left one negated
+    fn frames(range: std::ops::Range<usize>) -> Vec<f32> {
+        range.flat_map(|i| [i as f32, -(i as f32)]).collect()
+    }
+
+    fn pop_all(consumer: &mut FrameRingConsumer) -> Vec<f32> {
+        let mut out = vec![0.0; consumer.available_frames() * 2];
+        let frames = consumer.pop_partial_into(out.len() / 2, &mut out);
+        out.truncate(frames * 2);
+        out
+    }
+
+    #[test]
+    fn partial_frame_is_not_written() {
+        let (mut producer, mut consumer) = new_framering(2, 4, "test");
+        // two and a half frames
+        producer.push(&frames(0..3)[..5]);
+        producer.push(&frames(3..4));
+        assert_eq!(pop_all(&mut consumer), [0.0, -0.0, 1.0, -1.0, 3.0, -3.0]);
+    }
+
+    #[test]
+    fn drop_newest_keeps_frames_aligned() {
+        let (mut producer, mut consumer) = new_framering(2, 3, "test");
+        producer.push(&frames(0..2));
+        // one frame of room for a block of three
+        producer.push(&frames(2..5));
+        producer.push(&frames(5..6));
+        assert_eq!(pop_all(&mut consumer), frames(0..3));
+        assert_eq!(consumer.stats().snapshot().dropped_frames, 3);
+
+        // every frame still pairs up after wrapping around
+        producer.push(&frames(6..9));
+        assert_eq!(pop_all(&mut consumer), frames(6..9));
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_newest_frames() {
+        let (producer, mut consumer) = new_framering(2, 4, "test");
+        let mut producer = producer.with_overflow_policy(OverflowPolicy::DropOldest);
+        producer.push(&frames(0..3));
+        producer.push(&frames(3..6));
+        assert_eq!(consumer.available_frames(), 2);
+
+        // the consumer skips the two oldest, the held back frames follow
+        assert_eq!(pop_all(&mut consumer), frames(2..4));
+        producer.push(&frames(6..7));
+        assert_eq!(pop_all(&mut consumer), frames(4..7));
+        assert_eq!(consumer.stats().snapshot().dropped_frames, 2);
+    }
+
+    #[test]
+    fn drop_oldest_never_holds_more_than_a_ring() {
+        let (producer, mut consumer) = new_framering(2, 2, "test");
+        let mut producer = producer.with_overflow_policy(OverflowPolicy::DropOldest);
+        producer.push(&frames(0..2));
+        producer.push(&frames(2..7));
+        // everything queued is skipped, only the two newest are held back
+        assert_eq!(pop_all(&mut consumer), []);
+        producer.push(&[]);
+        assert_eq!(pop_all(&mut consumer), frames(5..7));
+        assert_eq!(consumer.stats().snapshot().dropped_frames, 5);
+    }
+
+    #[test]
+    fn drop_oldest_skips_only_what_is_still_held_back() {
+        let (producer, mut consumer) = new_framering(2, 4, "test");
+        let mut producer = producer.with_overflow_policy(OverflowPolicy::DropOldest);
+        producer.push(&frames(0..4));
+        producer.push(&frames(4..7));
+        let mut out = [0.0; 8];
+        // room for the three held back frames is made once
+        assert_eq!(consumer.pop_into(1, &mut out), 1);
+        assert_eq!(out[..2], frames(3..4));
+        assert_eq!(consumer.pop_partial_into(4, &mut out), 0);
+
+        // a flush that only partly fits asks for no more than it is short of
+        producer.push(&frames(7..9));
+        assert_eq!(consumer.stats().snapshot().dropped_frames, 3);
+        assert_eq!(consumer.available_frames(), 3);
+        assert_eq!(pop_all(&mut consumer), frames(5..8));
+        producer.push(&[]);
+        assert_eq!(pop_all(&mut consumer), frames(8..9));
+        assert_eq!(consumer.stats().snapshot().dropped_frames, 4);
+    }
+
+    #[test]
+    fn block_waits_for_room() {
+        let (producer, mut consumer) = new_framering(2, 2, "test");
+        let mut producer =
+            producer.with_overflow_policy(OverflowPolicy::Block(Duration::from_secs(5)));
+        let reader = thread::spawn(move || {
+            let mut read = Vec::new();
+            while read.len() < 12 {
+                read.extend(pop_all(&mut consumer));
+                thread::sleep(Duration::from_millis(1));
+            }
+            read
+        });
+        producer.push(&frames(0..6));
+        assert_eq!(reader.join().unwrap(), frames(0..6));
+        assert_eq!(producer.stats().snapshot().dropped_frames, 0);
+    }
+
+    #[test]
+    fn integer_ring_is_bit_transparent() {
+        let (mut producer, mut consumer) = new_framering::<i32>(2, 4, "test");
+        let samples = [i32::MIN, i32::MAX, 1, -1, 0x0123_4567, -0x0765_4321];
+        producer.push(&samples);
+        let mut out = [0; 6];
+        assert_eq!(consumer.pop_into(3, &mut out), 3);
+        assert_eq!(out, samples);
+    }
+
+    #[test]
+    fn transfer_converts_between_ring_types() {
+        let (mut producer, mut from) = new_framering::<i16>(2, 4, "i16");
+        let (mut to, mut consumer) = new_framering::<f32>(2, 2, "f32");
+        producer.push(&[i16::MIN, 16_384, 0, -16_384, 1, 2]);
+
+        // only as much as the destination has room for
+        assert_eq!(transfer(&mut from, &mut to, 8), 2);
+        assert_eq!(pop_all(&mut consumer), [-1.0, 0.5, 0.0, -0.5]);
+        assert_eq!(from.available_frames(), 1);
+        assert_eq!(consumer.stats().snapshot().dropped_frames, 0);
+    }
+
+    #[test]
+    fn chunks_hand_out_whole_frames_across_the_wrap() {
+        let (mut producer, mut consumer) = new_framering(2, 4, "test");
+        producer.push(&frames(0..3));
+        assert_eq!(pop_all(&mut consumer), frames(0..3));
+
+        // only three of the five frames fit, wrapping after the first
+        let mut chunk = producer.write_chunk(5);
+        assert_eq!(chunk.len(), 4);
+        assert_eq!(chunk.as_mut_slices().0.len(), 2);
+        for (i, frame) in (3..).zip(chunk.frames_mut()) {
+            frame.copy_from_slice(&[i as f32, -(i as f32)]);
+        }
+        chunk.commit(3);
+        assert_eq!(producer.stats().snapshot().dropped_frames, 1);
+
+        let chunk = consumer.read_chunk(8);
+        assert_eq!(chunk.len(), 3);
+        assert_eq!(
+            chunk.frames().flatten().copied().collect::<Vec<_>>(),
+            frames(3..6)
+        );
+        chunk.commit(1);
+        // what wasn't committed is still there
+        assert_eq!(pop_all(&mut consumer), frames(4..6));
+        let snapshot = consumer.stats().snapshot();
+        assert_eq!((snapshot.pushed_frames, snapshot.popped_frames), (6, 6));
+    }
+
+    fn write(producer: &mut FrameRingProducer, samples: &[f32]) {
+        let mut chunk = producer.write_chunk(samples.len() / 2);
+        assert_eq!(chunk.len(), samples.len() / 2);
+        for (frame, sample) in chunk.frames_mut().zip(samples.chunks_exact(2)) {
+            frame.copy_from_slice(sample);
+        }
+        chunk.commit_all();
+    }
+
+    #[test]
+    fn drop_oldest_write_chunk_keeps_the_newest_frames() {
+        let (producer, mut consumer) = new_framering(2, 4, "test");
+        let mut producer = producer.with_overflow_policy(OverflowPolicy::DropOldest);
+        write(&mut producer, &frames(0..3));
+        // one frame of room, the other two are held back
+        write(&mut producer, &frames(3..6));
+        // no room at all, this one queues up behind them
+        write(&mut producer, &frames(6..7));
+
+        // the consumer skips the three oldest, the held back frames follow
+        assert_eq!(pop_all(&mut consumer), frames(3..4));
+        producer.push(&[]);
+        assert_eq!(pop_all(&mut consumer), frames(4..7));
+        write(&mut producer, &frames(7..9));
+        assert_eq!(pop_all(&mut consumer), frames(7..9));
+        let snapshot = consumer.stats().snapshot();
+        assert_eq!(snapshot.dropped_frames, 3);
+        assert_eq!((snapshot.pushed_frames, snapshot.popped_frames), (9, 6));
+    }
+
+    #[test]
+    fn drop_oldest_write_chunk_holds_no_more_than_a_ring() {
+        let (producer, mut consumer) = new_framering(2, 2, "test");
+        let mut producer = producer.with_overflow_policy(OverflowPolicy::DropOldest);
+        write(&mut producer, &frames(0..2));
+        let mut chunk = producer.write_chunk(5);
+        assert_eq!(chunk.len(), 2);
+        chunk.as_mut_slices().0.copy_from_slice(&frames(5..7));
+        chunk.commit(1);
+
+        assert_eq!(pop_all(&mut consumer), frames(1..2));
+        producer.push(&[]);
+        assert_eq!(pop_all(&mut consumer), frames(5..6));
+        // three never fit, one was skipped for the committed frame
+        assert_eq!(consumer.stats().snapshot().dropped_frames, 4);
+    }
+
+    #[test]
+    fn block_write_chunk_waits_for_room() {
+        let (producer, mut consumer) = new_framering(2, 2, "test");
+        let mut producer =
+            producer.with_overflow_policy(OverflowPolicy::Block(Duration::from_secs(5)));
+        write(&mut producer, &frames(0..2));
+        let reader = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            pop_all(&mut consumer)
+        });
+        write(&mut producer, &frames(2..4));
+        assert_eq!(reader.join().unwrap(), frames(0..2));
+        assert_eq!(producer.stats().snapshot().dropped_frames, 0);
+    }
+
+    #[test]
+    fn block_gives_up_after_the_timeout() {
+        let (producer, mut consumer) = new_framering(2, 2, "test");
+        let mut producer =
+            producer.with_overflow_policy(OverflowPolicy::Block(Duration::from_millis(5)));
+        producer.push(&frames(0..3));
+        assert_eq!(pop_all(&mut consumer), frames(0..2));
+        assert_eq!(consumer.stats().snapshot().dropped_frames, 1);
+    }
+
+    #[test]
+    fn reads_count_short_and_empty() {
+        let (mut producer, mut consumer) = new_framering(2, 8, "test");
+        let mut out = [0.0; 8];
+        assert_eq!(consumer.pop_into(2, &mut out), 0);
+        producer.push(&frames(0..3));
+        // pop_into takes nothing from a short ring, the partial read takes what is there
+        assert_eq!(consumer.pop_into(4, &mut out), 0);
+        assert_eq!(consumer.pop_partial_into(4, &mut out), 3);
+        assert_eq!(consumer.pop_partial_into(1, &mut out), 0);
+        producer.push(&frames(3..5));
+        assert_eq!(consumer.pop_into(2, &mut out), 2);
+
+        let snapshot = consumer.stats().snapshot();
+        assert_eq!(snapshot.zero_reads, 2);
+        assert_eq!(snapshot.short_reads, 2);
+        assert_eq!((snapshot.pushed_frames, snapshot.popped_frames), (5, 5));
+    }
+
+    #[test]
+    fn watermarks_track_fill_on_both_ends() {
+        let (mut producer, mut consumer) = new_framering(2, 8, "test");
+        assert_eq!(producer.stats().snapshot().producer, Watermarks::default());
+
+        producer.push(&frames(0..3));
+        producer.push(&frames(3..8));
+        let mut out = [0.0; 12];
+        assert_eq!(consumer.pop_into(6, &mut out), 6);
+        producer.push(&frames(8..9));
+        assert_eq!(consumer.pop_into(3, &mut out), 3);
+
+        let snapshot = consumer.stats().snapshot();
+        assert_eq!(snapshot.producer, Watermarks { low: 3, high: 8 });
+        // measured before each read
+        assert_eq!(snapshot.consumer, Watermarks { low: 3, high: 8 });
+    }
+
+    #[test]
+    fn partial_read_takes_what_is_there() {
+        let (mut producer, mut consumer) = new_framering(2, 512, "test");
+        producer.push(&frames(0..511));
+        let mut out = vec![f32::NAN; 512 * 2];
+        assert_eq!(consumer.pop_partial_into(512, &mut out), 511);
+        assert_eq!(out[..511 * 2], frames(0..511));
+        // the frame that wasn't there is left alone
+        assert!(out[511 * 2..].iter().all(|sample| sample.is_nan()));
+        assert_eq!(consumer.stats().snapshot().padded_frames, 0);
+    }
+
+    #[test]
+    fn padded_read_zeroes_the_missing_tail() {
+        let (mut producer, mut consumer) = new_framering(2, 512, "test");
+        producer.push(&frames(0..511));
+        let mut out = vec![f32::NAN; 512 * 2];
+        assert_eq!(consumer.pop_padded_into(512, &mut out), 1);
+        assert_eq!(out[..511 * 2], frames(0..511));
+        assert_eq!(out[511 * 2..], [0.0, 0.0]);
+
+        // an empty ring pads the whole read
+        assert_eq!(consumer.pop_padded_into(512, &mut out), 512);
+        assert!(out.iter().all(|&sample| sample == 0.0));
+        let snapshot = consumer.stats().snapshot();
+        assert_eq!(snapshot.padded_frames, 513);
+        assert_eq!((snapshot.short_reads, snapshot.zero_reads), (1, 1));
+    }
 }
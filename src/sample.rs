@@ -0,0 +1,123 @@
+use std::any::TypeId;
+
+/// A sample type a frame ring can carry.
+///
+/// Integers are fixed point with full scale at ±1.0, like the ASIO formats.
+/// Conversions go through f64, which holds every i32 and f32 exactly, so
+/// converting to the same or a wider type never loses anything.
+pub trait Sample: Copy + Default + PartialEq + Send + 'static {
+    fn to_f64(self) -> f64;
+
+    /// Rounds and clips to the type's range
+    fn from_f64(value: f64) -> Self;
+
+    fn convert<T: Sample>(self) -> T {
+        T::from_f64(self.to_f64())
+    }
+}
+
+impl Sample for f32 {
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+}
+
+impl Sample for f64 {
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+}
+
+impl Sample for i16 {
+    fn to_f64(self) -> f64 {
+        self as f64 / 32_768.0
+    }
+
+    fn from_f64(value: f64) -> Self {
+        (value * 32_768.0)
+            .round()
+            .clamp(i16::MIN as f64, i16::MAX as f64) as i16
+    }
+}
+
+impl Sample for i32 {
+    fn to_f64(self) -> f64 {
+        self as f64 / 2_147_483_648.0
+    }
+
+    fn from_f64(value: f64) -> Self {
+        (value * 2_147_483_648.0)
+            .round()
+            .clamp(i32::MIN as f64, i32::MAX as f64) as i32
+    }
+}
+
+/// Convert `from` into the start of `to`, which must be at least as long.
+///
+/// Between slices of the same type this is a plain copy.
+pub fn convert_slice<A: Sample, B: Sample>(from: &[A], to: &mut [B]) {
+    let to = &mut to[..from.len()];
+    if TypeId::of::<A>() == TypeId::of::<B>() {
+        // SAFETY: A and B are the same type
+        let from = unsafe { std::slice::from_raw_parts(from.as_ptr().cast::<B>(), from.len()) };
+        to.copy_from_slice(from);
+        return;
+    }
+    for (to, &from) in to.iter_mut().zip(from) {
+        *to = from.convert();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integers_are_full_scale_at_one() {
+        assert_eq!(i16::MIN.to_f64(), -1.0);
+        assert_eq!(i32::MIN.to_f64(), -1.0);
+        assert_eq!(i16::from_f64(0.5), 16_384);
+        assert_eq!(i32::from_f64(-0.5), -1_073_741_824);
+    }
+
+    #[test]
+    fn conversion_clips() {
+        assert_eq!(i16::from_f64(1.0), i16::MAX);
+        assert_eq!(i16::from_f64(-2.0), i16::MIN);
+        assert_eq!(i32::from_f64(1.5), i32::MAX);
+    }
+
+    #[test]
+    fn widening_is_lossless() {
+        for value in [i16::MIN, -1, 0, 1, 12_345, i16::MAX] {
+            let wide: i32 = value.convert();
+            assert_eq!(wide, (value as i32) << 16);
+            assert_eq!(wide.convert::<i16>(), value);
+            assert_eq!(value.convert::<f32>().convert::<i16>(), value);
+        }
+        for value in [i32::MIN, -1, 1, 0x0123_4567, i32::MAX] {
+            assert_eq!(value.convert::<f64>().convert::<i32>(), value);
+        }
+    }
+
+    #[test]
+    fn convert_slice_copies_the_same_type() {
+        let from = [f32::MIN_POSITIVE, -0.0, 1.5];
+        let mut to = [0.0f32; 4];
+        convert_slice(&from, &mut to);
+        assert_eq!(to[..3], from);
+        assert_eq!(to[3], 0.0);
+
+        let mut ints = [0i16; 3];
+        convert_slice(&[-1.0f64, 0.25, 2.0], &mut ints);
+        assert_eq!(ints, [i16::MIN, 8_192, i16::MAX]);
+    }
+}
@@ -0,0 +1,186 @@
+use anyhow::Result;
+use regex::Regex;
+use std::{fmt, str::FromStr};
+
+/// Picks one driver or device out of the names a backend enumerates.
+///
+/// Parsed from a single string:
+/// - `2` picks by position in the enumeration order
+/// - `=Name` matches the whole name exactly
+/// - `/pattern/` matches a regular expression anywhere in the name
+/// - anything else matches a case-insensitive substring
+#[derive(Clone, Debug)]
+pub enum DriverSelector {
+    Exact(String),
+    Substring(String),
+    Regex(Regex),
+    Index(usize),
+}
+
+impl DriverSelector {
+    /// Index into `names` of the first match.
+    ///
+    /// Without a selector there must be exactly one name to pick. The error
+    /// lists everything that was available so the user can choose.
+    pub fn select(selector: Option<&Self>, names: &[String]) -> Result<usize> {
+        let found = match selector {
+            Some(DriverSelector::Index(index)) => (*index < names.len()).then_some(*index),
+            Some(selector) => names.iter().position(|name| selector.matches(name)),
+            None if names.len() == 1 => Some(0),
+            None => None,
+        };
+        found.ok_or_else(|| {
+            let wanted = match selector {
+                Some(selector) => format!("no driver matches {}", selector),
+                None if names.is_empty() => "no drivers installed".to_string(),
+                None => "more than one driver installed, pick one".to_string(),
+            };
+            anyhow::anyhow!("{}\n\nAvailable drivers:\n{}", wanted, list(names))
+        })
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            DriverSelector::Exact(exact) => name == exact,
+            DriverSelector::Substring(part) => name.to_lowercase().contains(&part.to_lowercase()),
+            DriverSelector::Regex(regex) => regex.is_match(name),
+            // picked by position in `select`
+            DriverSelector::Index(_) => false,
+        }
+    }
+}
+
+fn list(names: &[String]) -> String {
+    if names.is_empty() {
+        return "  (none)".to_string();
+    }
+    names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| format!("  {}: {}", i, name))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl FromStr for DriverSelector {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        if let Ok(index) = value.parse() {
+            return Ok(DriverSelector::Index(index));
+        }
+        if let Some(exact) = value.strip_prefix('=') {
+            return Ok(DriverSelector::Exact(exact.to_string()));
+        }
+        if let Some(pattern) = value
+            .strip_prefix('/')
+            .and_then(|rest| rest.strip_suffix('/'))
+        {
+            let regex = Regex::new(pattern)
+                .map_err(|e| anyhow::anyhow!("invalid driver pattern {}: {}", value, e))?;
+            return Ok(DriverSelector::Regex(regex));
+        }
+        anyhow::ensure!(!value.is_empty(), "empty driver name");
+        Ok(DriverSelector::Substring(value.to_string()))
+    }
+}
+
+impl fmt::Display for DriverSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DriverSelector::Exact(exact) => write!(f, "\"{}\"", exact),
+            DriverSelector::Substring(part) => write!(f, "*{}*", part),
+            DriverSelector::Regex(regex) => write!(f, "/{}/", regex.as_str()),
+            DriverSelector::Index(index) => write!(f, "index {}", index),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names() -> Vec<String> {
+        ["ASIO4ALL v2", "Focusrite USB ASIO", "ASIO Fireface USB"]
+            .map(String::from)
+            .to_vec()
+    }
+
+    fn select(selector: &str) -> Result<usize> {
+        DriverSelector::select(Some(&selector.parse()?), &names())
+    }
+
+    #[test]
+    fn number_picks_by_position() {
+        assert!(matches!("2".parse(), Ok(DriverSelector::Index(2))));
+        assert_eq!(select("2").unwrap(), 2);
+        let error = select("3").unwrap_err().to_string();
+        assert!(error.starts_with("no driver matches index 3"), "{error}");
+    }
+
+    #[test]
+    fn equals_matches_whole_name() {
+        assert_eq!(select("=Focusrite USB ASIO").unwrap(), 1);
+        // a part of the name or a different case is not enough
+        assert!(select("=Focusrite").is_err());
+        assert!(select("=focusrite usb asio").is_err());
+    }
+
+    #[test]
+    fn slashes_match_pattern() {
+        assert_eq!(select("/^ASIO F/").unwrap(), 2);
+        assert_eq!(select("/v\\d$/").unwrap(), 0);
+        assert!(select("/^Fireface/").is_err());
+    }
+
+    #[test]
+    fn anything_else_matches_substring() {
+        assert_eq!(select("fireface").unwrap(), 2);
+        // first match wins
+        assert_eq!(select("usb").unwrap(), 1);
+        // a lone slash is a name, not an unterminated pattern
+        assert!(matches!("/".parse(), Ok(DriverSelector::Substring(_))));
+    }
+
+    #[test]
+    fn invalid_pattern_is_rejected() {
+        let error = "/(unclosed/".parse::<DriverSelector>().unwrap_err();
+        let error = error.to_string();
+        assert!(
+            error.starts_with("invalid driver pattern /(unclosed/"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn empty_name_is_rejected() {
+        let error = "".parse::<DriverSelector>().unwrap_err();
+        assert_eq!(error.to_string(), "empty driver name");
+    }
+
+    #[test]
+    fn error_lists_available_drivers() {
+        let error = select("RME").unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "no driver matches *RME*\n\nAvailable drivers:\n  0: ASIO4ALL v2\n  1: Focusrite USB ASIO\n  2: ASIO Fireface USB"
+        );
+    }
+
+    #[test]
+    fn no_selector_needs_exactly_one_driver() {
+        assert_eq!(DriverSelector::select(None, &names()[..1]).unwrap(), 0);
+        let error = DriverSelector::select(None, &names())
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.starts_with("more than one driver installed"),
+            "{error}"
+        );
+        let error = DriverSelector::select(None, &[]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "no drivers installed\n\nAvailable drivers:\n  (none)"
+        );
+    }
+}
@@ -0,0 +1,415 @@
+use anyhow::Result;
+use std::{
+    f64::consts::TAU,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::backend::{AudioSink, AudioSource};
+use crate::clock::{Clock, Pacing, Timeline};
+use crate::ring::{FrameRingConsumer, FrameRingProducer};
+
+/// Frames per period when the caller doesn't pick a size
+const DEFAULT_PERIOD: usize = 256;
+
+/// Fills periods for a [`SimSource`].
+pub trait Signal: Send + 'static {
+    /// Write `out.len() / channels` interleaved frames
+    fn fill(&mut self, out: &mut [f32], channels: usize);
+}
+
+/// The same sine on every channel.
+pub struct Sine {
+    step: f64,
+    phase: f64,
+    amplitude: f32,
+}
+
+impl Signal for Sine {
+    fn fill(&mut self, out: &mut [f32], channels: usize) {
+        for frame in out.chunks_exact_mut(channels) {
+            frame.fill(self.phase.sin() as f32 * self.amplitude);
+            self.phase = (self.phase + self.step) % TAU;
+        }
+    }
+}
+
+/// Uniform white noise, independent per channel and repeatable for a given seed.
+pub struct Noise {
+    state: u64,
+    amplitude: f32,
+}
+
+impl Signal for Noise {
+    fn fill(&mut self, out: &mut [f32], _channels: usize) {
+        for sample in out {
+            // xorshift64
+            self.state ^= self.state << 13;
+            self.state ^= self.state >> 7;
+            self.state ^= self.state << 17;
+            let unit = (self.state >> 40) as f32 / (1u64 << 24) as f32;
+            *sample = (unit * 2.0 - 1.0) * self.amplitude;
+        }
+    }
+}
+
+/// A synthetic source running on its own [`Clock`] instead of hardware.
+pub struct SimSource<S: Signal> {
+    signal: Option<S>,
+    sample_rate: usize,
+    channels: usize,
+    period: usize,
+    pacing: Pacing,
+    drift_ppm: f64,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<S>>,
+}
+
+pub type SineSource = SimSource<Sine>;
+pub type NoiseSource = SimSource<Noise>;
+
+impl SineSource {
+    pub fn new(sample_rate: usize, channels: usize, frequency: f64) -> Self {
+        SimSource::with_signal(
+            Sine {
+                step: TAU * frequency / sample_rate as f64,
+                phase: 0.0,
+                amplitude: 0.5,
+            },
+            sample_rate,
+            channels,
+        )
+    }
+}
+
+impl NoiseSource {
+    pub fn new(sample_rate: usize, channels: usize, seed: u64) -> Self {
+        SimSource::with_signal(
+            Noise {
+                // xorshift never leaves zero
+                state: seed.max(1),
+                amplitude: 0.5,
+            },
+            sample_rate,
+            channels,
+        )
+    }
+}
+
+impl<S: Signal> SimSource<S> {
+    pub fn with_signal(signal: S, sample_rate: usize, channels: usize) -> Self {
+        Self {
+            signal: Some(signal),
+            sample_rate,
+            channels,
+            period: DEFAULT_PERIOD,
+            pacing: Pacing::RealTime,
+            drift_ppm: 0.0,
+            running: Arc::new(AtomicBool::new(false)),
+            thread: None,
+        }
+    }
+
+    /// Frames pushed per period
+    pub fn with_period(mut self, frames: usize) -> Self {
+        self.period = frames;
+        self
+    }
+
+    pub fn with_pacing(mut self, pacing: Pacing) -> Self {
+        self.pacing = pacing;
+        self
+    }
+
+    /// Run this source's clock off nominal, see [`Clock::with_drift_ppm`]
+    pub fn with_drift_ppm(mut self, ppm: f64) -> Self {
+        self.drift_ppm = ppm;
+        self
+    }
+}
+
+impl<S: Signal> AudioSource for SimSource<S> {
+    fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn block_size(&self) -> usize {
+        self.period
+    }
+
+    fn start(&mut self, mut ring: FrameRingProducer) -> Result<()> {
+        self.stop()?;
+
+        let Some(mut signal) = self.signal.take() else {
+            anyhow::bail!("simulated source lost its signal");
+        };
+        let channels = self.channels;
+        let period = self.period;
+        let running = self.running.clone();
+        let mut clock = Clock::new(self.pacing.clone(), self.sample_rate)
+            .with_drift_ppm(self.drift_ppm)
+            .with_running(running.clone());
+        running.store(true, Ordering::Relaxed);
+
+        let thread = thread::Builder::new()
+            .name("sim-source".into())
+            .spawn(move || {
+                let mut block = vec![0.0f32; period * channels];
+                clock.reset();
+                while running.load(Ordering::Relaxed) {
+                    if !clock.is_paced() {
+                        while ring.available_frames() < period && running.load(Ordering::Relaxed) {
+                            thread::sleep(Duration::from_millis(1));
+                        }
+                    }
+
+                    signal.fill(&mut block, channels);
+                    ring.push(&block);
+                    clock.wait(period);
+                }
+                signal
+            })?;
+        self.thread = Some(thread);
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            match thread.join() {
+                // keep the phase/seed so a restart continues the signal
+                Ok(signal) => self.signal = Some(signal),
+                Err(_) => anyhow::bail!("simulated source thread panicked"),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: Signal> Drop for SimSource<S> {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+/// Counters kept by a [`NullSink`], readable while it runs.
+#[derive(Debug, Default)]
+pub struct NullSinkStats {
+    /// Frames taken from the ring
+    pub frames: AtomicU64,
+    /// Periods that found too few frames in the ring and were skipped
+    pub underruns: AtomicU64,
+}
+
+/// Discards everything it is fed, one period per tick of its own [`Clock`].
+pub struct NullSink {
+    sample_rate: usize,
+    channels: usize,
+    period: usize,
+    pacing: Pacing,
+    drift_ppm: f64,
+    stats: Arc<NullSinkStats>,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl NullSink {
+    pub fn new(sample_rate: usize, channels: usize) -> Self {
+        Self {
+            sample_rate,
+            channels,
+            period: DEFAULT_PERIOD,
+            pacing: Pacing::RealTime,
+            drift_ppm: 0.0,
+            stats: Arc::new(NullSinkStats::default()),
+            running: Arc::new(AtomicBool::new(false)),
+            thread: None,
+        }
+    }
+
+    /// Frames consumed per period
+    pub fn with_period(mut self, frames: usize) -> Self {
+        self.period = frames;
+        self
+    }
+
+    pub fn with_pacing(mut self, pacing: Pacing) -> Self {
+        self.pacing = pacing;
+        self
+    }
+
+    /// Run this sink's clock off nominal, see [`Clock::with_drift_ppm`]
+    pub fn with_drift_ppm(mut self, ppm: f64) -> Self {
+        self.drift_ppm = ppm;
+        self
+    }
+
+    pub fn stats(&self) -> Arc<NullSinkStats> {
+        self.stats.clone()
+    }
+}
+
+impl AudioSink for NullSink {
+    fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn block_size(&self) -> usize {
+        self.period
+    }
+
+    fn is_paced(&self) -> bool {
+        self.pacing != Pacing::Unpaced
+    }
+
+    fn timeline(&self) -> Option<Timeline> {
+        self.pacing.timeline()
+    }
+
+    fn start(&mut self, mut ring: FrameRingConsumer) -> Result<()> {
+        self.stop()?;
+
+        let channels = self.channels;
+        let period = self.period;
+        let stats = self.stats.clone();
+        let running = self.running.clone();
+        let mut clock = Clock::new(self.pacing.clone(), self.sample_rate)
+            .with_drift_ppm(self.drift_ppm)
+            .with_running(running.clone());
+        running.store(true, Ordering::Relaxed);
+
+        let thread = thread::Builder::new()
+            .name("null-sink".into())
+            .spawn(move || {
+                let mut block = vec![0.0f32; period * channels];
+                clock.reset();
+                while running.load(Ordering::Relaxed) {
+                    if clock.is_paced() {
+                        clock.wait(period);
+                    }
+
+                    // unpaced, only a read that can succeed counts against the ring's stats
+                    let frames = if clock.is_paced() || ring.available_frames() >= period {
+                        ring.pop_into(period, &mut block)
+                    } else {
+                        0
+                    };
+                    stats.frames.fetch_add(frames as u64, Ordering::Relaxed);
+                    if frames < period {
+                        if clock.is_paced() {
+                            stats.underruns.fetch_add(1, Ordering::Relaxed);
+                        } else {
+                            thread::sleep(Duration::from_millis(1));
+                        }
+                    }
+                }
+            })?;
+        self.thread = Some(thread);
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for NullSink {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::bridge::{Bridge, BridgeStatus};
+    use crate::drift::DriftConfig;
+
+    const RATE: usize = 48_000;
+
+    /// Play a 440 Hz tone from a source `ppm` off the sink's clock through a
+    /// bridge on a virtual timeline until `secs` of audio have gone to the
+    /// sink, reporting the sink's underruns and the bridge status every tenth
+    /// of a second
+    pub(crate) fn run_drifting(
+        ppm: f64,
+        drift: Option<DriftConfig>,
+        secs: f64,
+        mut check: impl FnMut(f64, u64, &BridgeStatus),
+    ) {
+        let timeline = Timeline::new();
+        let pacing = Pacing::Virtual(timeline.clone());
+        let source = SineSource::new(RATE, 2, 440.0)
+            .with_pacing(pacing.clone())
+            .with_period(1024)
+            .with_drift_ppm(ppm);
+        let sink = NullSink::new(RATE, 2).with_pacing(pacing).with_period(4096);
+        let stats = sink.stats();
+        let mut bridge = Bridge::builder()
+            .source(source)
+            .sink(sink)
+            .drift_compensation(drift)
+            .build()
+            .unwrap();
+        bridge.start().unwrap();
+        loop {
+            timeline.advance(Duration::from_millis(100));
+            let status = bridge.status();
+            assert!(status.resampler_alive);
+            let played = stats.frames.load(Ordering::Relaxed) as f64 / RATE as f64;
+            check(played, stats.underruns.load(Ordering::Relaxed), &status);
+            if played >= secs {
+                break;
+            }
+        }
+        bridge.stop().unwrap();
+    }
+
+    #[test]
+    fn matched_clocks_never_underrun() {
+        run_drifting(0.0, None, 10.0, |_, underruns, status| {
+            assert!(status.drift.is_none());
+            assert_eq!(underruns, 0);
+        });
+    }
+
+    #[test]
+    fn virtual_timeline_takes_periods_in_turn() {
+        let timeline = Timeline::new();
+        let mut sink = NullSink::new(RATE, 2)
+            .with_pacing(Pacing::Virtual(timeline.clone()))
+            .with_period(480);
+        let stats = sink.stats();
+        let (mut producer, consumer) = crate::ring::new_framering(2, 4800, "test");
+        sink.start(consumer).unwrap();
+
+        producer.push(&[0.0; 2 * 1000]);
+        // nothing is due before the end of the first period
+        timeline.advance(Duration::from_millis(9));
+        assert_eq!(stats.frames.load(Ordering::Relaxed), 0);
+        timeline.advance(Duration::from_millis(1));
+        assert_eq!(stats.frames.load(Ordering::Relaxed), 480);
+        // the third period finds only 40 frames left
+        timeline.advance(Duration::from_millis(20));
+        assert_eq!(stats.frames.load(Ordering::Relaxed), 960);
+        assert_eq!(stats.underruns.load(Ordering::Relaxed), 1);
+        assert_eq!(timeline.now(), Duration::from_millis(30));
+        sink.stop().unwrap();
+    }
+}
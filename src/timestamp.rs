@@ -0,0 +1,278 @@
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::collections::VecDeque;
+
+/// When a source period was captured, by the device's clock and the system's.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Timestamp {
+    /// Frames the source had offered to its ring before this period
+    pub frame: u64,
+    /// Device sample counter at the start of the period
+    pub sample_position: u64,
+    /// System time at the start of the period, in nanoseconds
+    pub system_time_ns: u64,
+}
+
+/// Real-time side of a timestamp queue, pushed from the audio callback.
+pub struct TimestampProducer {
+    producer: Producer<Timestamp>,
+}
+
+/// Reading side of a timestamp queue.
+pub struct TimestampConsumer {
+    consumer: Consumer<Timestamp>,
+}
+
+/// Queue for the timestamps of up to `capacity` periods.
+pub fn new_timestamp_queue(capacity: usize) -> (TimestampProducer, TimestampConsumer) {
+    let (producer, consumer) = RingBuffer::new(capacity);
+    (
+        TimestampProducer { producer },
+        TimestampConsumer { consumer },
+    )
+}
+
+impl TimestampProducer {
+    /// Never blocks; a timestamp that doesn't fit is dropped and false returned
+    pub fn push(&mut self, timestamp: Timestamp) -> bool {
+        self.producer.push(timestamp).is_ok()
+    }
+}
+
+impl TimestampConsumer {
+    pub fn pop(&mut self) -> Option<Timestamp> {
+        self.consumer.pop().ok()
+    }
+}
+
+/// A device clock measured against the system clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ClockEstimate {
+    /// Device frames per second of system time
+    pub rate: f64,
+    /// How far `rate` is from the nominal rate, in parts per million
+    pub ppm: f64,
+    /// RMS distance of the timestamps from the fitted line, in seconds
+    pub jitter_secs: f64,
+    /// System time the fit covers, in seconds
+    pub span_secs: f64,
+    /// Timestamps in the fit
+    pub points: usize,
+}
+
+/// Least-squares fit of a device's sample position against system time.
+///
+/// Keeps the timestamps of the last `window_secs` of system time. A jump in
+/// either counter, like a driver resync, restarts the fit.
+pub struct ClockEstimator {
+    nominal_rate: f64,
+    window_ns: u64,
+    /// (system time ns, sample position)
+    points: VecDeque<(u64, u64)>,
+}
+
+/// Largest difference between the two clocks that still counts as the same
+/// timeline, in seconds
+const MAX_STEP_ERROR_SECS: f64 = 0.1;
+
+impl ClockEstimator {
+    pub fn new(nominal_rate: usize, window_secs: f64) -> Self {
+        Self {
+            nominal_rate: nominal_rate as f64,
+            window_ns: (window_secs * 1e9) as u64,
+            points: VecDeque::new(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.points.clear();
+    }
+
+    pub fn update(&mut self, timestamp: &Timestamp) {
+        let point = (timestamp.system_time_ns, timestamp.sample_position);
+        if let Some(&(time, position)) = self.points.back() {
+            let continues = point.0 > time && point.1 >= position && {
+                let secs = (point.0 - time) as f64 * 1e-9;
+                let frames = (point.1 - position) as f64;
+                (frames / self.nominal_rate - secs).abs() < MAX_STEP_ERROR_SECS
+            };
+            if !continues {
+                self.points.clear();
+            }
+        }
+
+        self.points.push_back(point);
+        while let Some(&(oldest, _)) = self.points.front() {
+            if point.0 - oldest <= self.window_ns {
+                break;
+            }
+            self.points.pop_front();
+        }
+    }
+
+    /// `None` until the window has two timestamps at different times
+    pub fn estimate(&self) -> Option<ClockEstimate> {
+        let &(time0, position0) = self.points.front()?;
+        let &(time1, _) = self.points.back()?;
+        if time1 == time0 {
+            return None;
+        }
+
+        // relative to the first point, so the sums stay well inside f64 precision
+        let xy = || {
+            self.points.iter().map(move |&(time, position)| {
+                ((time - time0) as f64 * 1e-9, (position - position0) as f64)
+            })
+        };
+        let n = self.points.len() as f64;
+        let (sx, sy) = xy().fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x, sy + y));
+        let (mx, my) = (sx / n, sy / n);
+        let (sxx, sxy) = xy().fold((0.0, 0.0), |(sxx, sxy), (x, y)| {
+            (sxx + (x - mx) * (x - mx), sxy + (x - mx) * (y - my))
+        });
+        let rate = sxy / sxx;
+        let intercept = my - rate * mx;
+        let residual = xy()
+            .map(|(x, y)| {
+                let error = y - (intercept + rate * x);
+                error * error
+            })
+            .sum::<f64>()
+            / n;
+
+        Some(ClockEstimate {
+            rate,
+            ppm: (rate / self.nominal_rate - 1.0) * 1e6,
+            jitter_secs: residual.sqrt() / rate,
+            span_secs: (time1 - time0) as f64 * 1e-9,
+            points: self.points.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: usize = 48_000;
+    const PERIOD: u64 = 512;
+
+    /// Timestamp of the `n`th period of a device running `ppm` off nominal,
+    /// starting at `start_ns` of system time, with `jitter_ns` added
+    fn timestamp(n: u64, ppm: f64, start_ns: u64, jitter_ns: i64) -> Timestamp {
+        let sample_position = n * PERIOD;
+        let secs = sample_position as f64 / (RATE as f64 * (1.0 + ppm * 1e-6));
+        Timestamp {
+            frame: sample_position,
+            sample_position,
+            system_time_ns: (start_ns as i64 + (secs * 1e9) as i64 + jitter_ns) as u64,
+        }
+    }
+
+    #[test]
+    fn needs_two_points_in_time() {
+        let mut estimator = ClockEstimator::new(RATE, 10.0);
+        assert!(estimator.estimate().is_none());
+        estimator.update(&timestamp(0, 0.0, 1_000, 0));
+        assert!(estimator.estimate().is_none());
+        estimator.update(&timestamp(1, 0.0, 1_000, 0));
+        assert_eq!(estimator.estimate().unwrap().points, 2);
+    }
+
+    #[test]
+    fn measures_a_known_offset() {
+        for ppm in [-250.0, 0.0, 80.0] {
+            let mut estimator = ClockEstimator::new(RATE, 10.0);
+            for n in 0..500 {
+                estimator.update(&timestamp(n, ppm, 5_000_000_000, 0));
+            }
+            let estimate = estimator.estimate().unwrap();
+            assert!((estimate.ppm - ppm).abs() < 0.01, "{ppm}: {estimate:?}");
+            assert!((estimate.rate - RATE as f64 * (1.0 + ppm * 1e-6)).abs() < 1e-3);
+            assert!(estimate.jitter_secs < 1e-8, "{estimate:?}");
+            assert_eq!(estimate.points, 500);
+        }
+    }
+
+    #[test]
+    fn jitter_is_the_rms_distance_from_the_line() {
+        let mut estimator = ClockEstimator::new(RATE, 10.0);
+        for n in 0..1000 {
+            let jitter = if n % 2 == 0 { 50_000 } else { -50_000 };
+            estimator.update(&timestamp(n, 100.0, 1_000_000, jitter));
+        }
+        let estimate = estimator.estimate().unwrap();
+        // alternating noise leaves the slope alone
+        assert!((estimate.ppm - 100.0).abs() < 0.5, "{estimate:?}");
+        assert!((estimate.jitter_secs - 50e-6).abs() < 1e-6, "{estimate:?}");
+    }
+
+    #[test]
+    fn keeps_only_the_window() {
+        let mut estimator = ClockEstimator::new(RATE, 1.0);
+        // three seconds of periods
+        for n in 0..282 {
+            estimator.update(&timestamp(n, 0.0, 0, 0));
+        }
+        let estimate = estimator.estimate().unwrap();
+        let period_secs = PERIOD as f64 / RATE as f64;
+        assert!(estimate.span_secs <= 1.0, "{estimate:?}");
+        assert!(estimate.span_secs > 1.0 - period_secs, "{estimate:?}");
+        assert_eq!(estimate.points, (1.0 / period_secs) as usize + 1);
+    }
+
+    /// `last` moved on by `frames` of sample position and `ns` of system time
+    fn step(last: &Timestamp, frames: i64, ns: i64) -> Timestamp {
+        Timestamp {
+            frame: last.frame + PERIOD,
+            sample_position: last.sample_position.wrapping_add_signed(frames),
+            system_time_ns: last.system_time_ns.wrapping_add_signed(ns),
+        }
+    }
+
+    #[test]
+    fn restarts_after_a_jump() {
+        let mut estimator = ClockEstimator::new(RATE, 10.0);
+        for n in 0..100 {
+            estimator.update(&timestamp(n, 0.0, 0, 0));
+        }
+        let period_ns = (PERIOD * 1_000_000_000 / RATE as u64) as i64;
+        let last = timestamp(99, 0.0, 0, 0);
+
+        // a period on the system clock, but a driver resync moved the sample
+        // position a second further
+        let jumped = step(&last, PERIOD as i64 + RATE as i64, period_ns);
+        estimator.update(&jumped);
+        assert!(estimator.estimate().is_none());
+        estimator.update(&step(&jumped, PERIOD as i64, period_ns));
+        assert_eq!(estimator.estimate().unwrap().points, 2);
+
+        // just under the limit still counts as the same timeline
+        let mut estimator = ClockEstimator::new(RATE, 10.0);
+        estimator.update(&last);
+        estimator.update(&step(&last, PERIOD as i64 + RATE as i64 / 20, period_ns));
+        assert_eq!(estimator.estimate().unwrap().points, 2);
+    }
+
+    #[test]
+    fn restarts_when_either_clock_goes_back() {
+        let last = timestamp(10, 0.0, 1_000_000_000, 0);
+        for back in [
+            step(&last, -(PERIOD as i64), 10_000),
+            step(&last, 1, -10_000),
+        ] {
+            let mut estimator = ClockEstimator::new(RATE, 10.0);
+            for n in 0..=10 {
+                estimator.update(&timestamp(n, 0.0, 1_000_000_000, 0));
+            }
+            estimator.update(&back);
+            assert!(estimator.estimate().is_none());
+            assert_eq!(estimator.points.len(), 1);
+        }
+
+        let mut estimator = ClockEstimator::new(RATE, 10.0);
+        estimator.update(&timestamp(0, 0.0, 0, 0));
+        estimator.update(&timestamp(1, 0.0, 0, 0));
+        estimator.reset();
+        assert!(estimator.estimate().is_none());
+    }
+}
@@ -1,3 +1,5 @@
+#![allow(unused_imports)]
+
 pub use std::println as info;
 pub use std::println as debug;
 pub use std::println as warn;
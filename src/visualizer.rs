@@ -75,6 +75,12 @@ impl AudioVisualizer {
     }
 }
 
+impl Default for AudioVisualizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Drop for AudioVisualizer {
     fn drop(&mut self) {
         print!("\r");
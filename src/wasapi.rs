@@ -1,10 +1,10 @@
 use anyhow::Result;
-use audioadapter_buffers::direct::InterleavedSlice;
-use rtrb::{chunks::ChunkError, Consumer, Producer, RingBuffer};
-use rubato::{Async, FixedAsync, Resampler, SincInterpolationParameters};
 use std::{
-    sync::Arc,
-    thread::{self, sleep_ms, yield_now},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread::{self, JoinHandle},
 };
 use wasapi::*;
 use windows::Win32::{
@@ -13,65 +13,170 @@ use windows::Win32::{
         AUDCLNT_E_ENDPOINT_CREATE_FAILED, AUDCLNT_E_EXCLUSIVE_MODE_NOT_ALLOWED,
         AUDCLNT_E_UNSUPPORTED_FORMAT,
     },
-    System::Threading::{
-        GetCurrentThread, SetThreadPriority, INFINITE, THREAD_PRIORITY_TIME_CRITICAL,
-    },
+    System::Threading::{GetCurrentThread, SetThreadPriority, THREAD_PRIORITY_TIME_CRITICAL},
 };
 
-use crate::ring::{new_framering, FrameRingConsumer};
+use crate::backend::{AudioSink, AudioSource};
+use crate::convert::append_samples_as_bytes;
+use crate::devices::{EndpointDirection, WasapiEndpointInfo};
+use crate::ring::{FrameRingConsumer, FrameRingProducer};
 
 use std::println as info;
 use std::println as debug;
 use std::println as warn;
 use std::println as error;
 
-/// Convert f32 samples to the hardware format (optimized)
-fn convert_samples_to_bytes(
-    samples: &[f32],
-    byte_buffer: &mut Vec<u8>,
-    bits_per_sample: u16,
-    is_float: bool,
-) {
-    byte_buffer.clear();
-
-    if bits_per_sample == 32 && is_float {
-        // Fast path: direct memory copy for f32
-        let bytes =
-            unsafe { std::slice::from_raw_parts(samples.as_ptr() as *const u8, samples.len() * 4) };
-        byte_buffer.extend_from_slice(bytes);
+/// How long the render loop waits for a device event before re-checking for shutdown
+const EVENT_TIMEOUT_MS: u32 = 100;
+
+/// Rates tried in exclusive mode when the caller has no preference, best first
+pub const CANDIDATE_RATES: [usize; 6] = [192_000, 176_400, 96_000, 88_200, 48_000, 44_100];
+
+/// The exclusive-mode format the render loop converts to
+fn render_format(sample_rate: usize, channels: usize) -> WaveFormat {
+    WaveFormat::new(
+        32, // container bits
+        32, // valid bits
+        &SampleType::Int,
+        sample_rate,
+        channels,
+        None, // mask derived from the channel count
+    )
+}
+
+/// Pick the first of `rates` (or of [`CANDIDATE_RATES`] if empty) the default
+/// render endpoint accepts in exclusive mode, falling back to its mix format rate.
+fn select_sample_rate(rates: &[usize], channels: usize) -> Result<usize> {
+    let enumerator = DeviceEnumerator::new()?;
+    let device = enumerator.get_default_device(&Direction::Render)?;
+    let audio_client = device.get_iaudioclient()?;
+
+    let rates = if rates.is_empty() {
+        &CANDIDATE_RATES[..]
     } else {
-        match bits_per_sample {
-            16 => {
-                for &sample in samples {
-                    let val = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
-                    byte_buffer.extend_from_slice(&val.to_le_bytes());
-                }
-            }
-            24 => {
-                for &sample in samples {
-                    let val = (sample.clamp(-1.0, 1.0) * 8_388_607.0) as i32;
-                    byte_buffer.extend_from_slice(&val.to_le_bytes()[..3]);
-                }
-            }
-            32 => {
-                for &sample in samples {
-                    let val = (sample.clamp(-1.0, 1.0) * i32::MAX as f32) as i32;
-                    byte_buffer.extend_from_slice(&val.to_le_bytes());
-                }
-            }
-            _ => unreachable!("Unsupported bit depth: {}", bits_per_sample),
+        rates
+    };
+    for &rate in rates {
+        let format = render_format(rate, channels);
+        match audio_client.is_supported(&format, &ShareMode::Exclusive) {
+            Ok(_) => return Ok(rate),
+            Err(e) => debug!("{} Hz not supported: {:?}", rate, e),
         }
     }
+
+    let mix_rate = audio_client.get_mixformat()?.get_samplespersec() as usize;
+    warn!(
+        "None of {:?} Hz supported in exclusive mode, using mix format rate {} Hz",
+        rates, mix_rate
+    );
+    Ok(mix_rate)
+}
+
+/// Which endpoint a [`WasapiSource`] records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureMode {
+    /// The default capture endpoint, such as a microphone or line input
+    Endpoint,
+    /// Whatever the default render endpoint is playing
+    Loopback,
+}
+
+/// The shared-mode format captured frames are converted to, Windows does the conversion
+fn capture_format(sample_rate: usize, channels: usize) -> WaveFormat {
+    WaveFormat::new(32, 32, &SampleType::Float, sample_rate, channels, None)
 }
 
-pub fn start_wasapi(
-    mut asio_consumer: FrameRingConsumer,
+/// The default endpoint a capture in `mode` records from
+fn capture_device(mode: CaptureMode) -> Result<Device> {
+    let enumerator = DeviceEnumerator::new()?;
+    let direction = match mode {
+        CaptureMode::Endpoint => Direction::Capture,
+        // a render endpoint opened for capture is a loopback
+        CaptureMode::Loopback => Direction::Render,
+    };
+    Ok(enumerator.get_default_device(&direction)?)
+}
+
+/// Open the capture endpoint for `mode` in shared mode at `sample_rate`/`channels`.
+fn open_capture_client(
+    mode: CaptureMode,
     sample_rate: usize,
     channels: usize,
-) -> Result<()> {
-    // WASAPI requires COM initialized on the calling thread
+) -> Result<AudioClient> {
+    let device = capture_device(mode)?;
+    info!(
+        "Capturing from: {} ({:?})",
+        device.get_friendlyname()?,
+        mode
+    );
+
+    let mut audio_client = device.get_iaudioclient()?;
+    let (default_period, _min_period) = audio_client.get_device_period()?;
+    let stream_mode = StreamMode::EventsShared {
+        autoconvert: true,
+        buffer_duration_hns: default_period,
+    };
+    audio_client.initialize_client(
+        &capture_format(sample_rate, channels),
+        &Direction::Capture,
+        &stream_mode,
+    )?;
+    Ok(audio_client)
+}
+
+/// Every active render and capture endpoint with its mix format and device period.
+pub fn list_endpoints() -> Result<Vec<WasapiEndpointInfo>> {
     let _ = initialize_mta();
+    let enumerator = DeviceEnumerator::new()?;
+
+    let mut endpoints = Vec::new();
+    for (direction, kind) in [
+        (Direction::Render, EndpointDirection::Render),
+        (Direction::Capture, EndpointDirection::Capture),
+    ] {
+        let default_id = enumerator
+            .get_default_device(&direction)
+            .and_then(|device| device.get_id())
+            .ok();
+        for device in &enumerator.get_device_collection(&direction)? {
+            let device = device?;
+            let id = device.get_id()?;
+            let mut endpoint = WasapiEndpointInfo {
+                direction: kind,
+                name: device.get_friendlyname()?,
+                is_default: default_id.as_ref() == Some(&id),
+                id,
+                mix_rate: 0,
+                mix_channels: 0,
+                mix_bits: 0,
+                mix_float: false,
+                default_period: 0,
+                min_period: 0,
+                error: None,
+            };
+            let mut query = || -> Result<()> {
+                let audio_client = device.get_iaudioclient()?;
+                let mix = audio_client.get_mixformat()?;
+                let (default_period, min_period) = audio_client.get_device_period()?;
+                endpoint.mix_rate = mix.get_samplespersec() as usize;
+                endpoint.mix_channels = mix.get_nchannels() as usize;
+                endpoint.mix_bits = mix.get_validbitspersample();
+                endpoint.mix_float = matches!(mix.get_subformat()?, SampleType::Float);
+                endpoint.default_period = default_period;
+                endpoint.min_period = min_period;
+                Ok(())
+            };
+            if let Err(e) = query() {
+                endpoint.error = Some(format!("{:#}", e));
+            }
+            endpoints.push(endpoint);
+        }
+    }
+    Ok(endpoints)
+}
 
+/// Open the default render endpoint and initialize it for `sample_rate`/`channels`.
+fn open_render_client(sample_rate: usize, channels: usize) -> Result<(AudioClient, WaveFormat)> {
     let enumerator = DeviceEnumerator::new()?;
     let device = enumerator.get_default_device(&Direction::Render)?;
     info!("Using device: {}", device.get_friendlyname()?);
@@ -79,14 +184,7 @@ pub fn start_wasapi(
     let mut audio_client = device.get_iaudioclient()?;
 
     // Requested format (shared mode will autoconvert if needed)
-    let hw_format = WaveFormat::new(
-        32, // container bits
-        32, // valid bits
-        &SampleType::Int,
-        sample_rate,
-        channels,
-        Some(0x3),
-    );
+    let hw_format = render_format(sample_rate, channels);
 
     debug!("Requested format: {:?}", hw_format);
 
@@ -99,12 +197,6 @@ pub fn start_wasapi(
         min_period as f64 / 10_000.0
     );
 
-    // Evented shared mode with minimum period
-    let mode = StreamMode::EventsShared {
-        autoconvert: false,
-        buffer_duration_hns: min_period,
-    };
-
     // Evented exclusive
     let mode = StreamMode::EventsExclusive {
         period_hns: min_period,
@@ -155,6 +247,141 @@ pub fn start_wasapi(
         }
     }
 
+    Ok((audio_client, hw_format))
+}
+
+/// The thread a WASAPI stream runs on, with the COM objects it opens.
+///
+/// Dropping it stops the stream.
+struct StreamThread {
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl StreamThread {
+    fn new() -> Self {
+        Self {
+            running: Arc::new(AtomicBool::new(false)),
+            thread: None,
+        }
+    }
+
+    /// Run `stream` on a new COM thread named `wasapi-{name}` until it returns
+    /// or `stop` is called, and wait for it to report that it started.
+    ///
+    /// `stream` sends `Ok` once the device is running; an error before that
+    /// is returned from here.
+    fn start(
+        &mut self,
+        name: &'static str,
+        stream: impl FnOnce(&AtomicBool, &mpsc::Sender<Result<()>>) -> Result<()> + Send + 'static,
+    ) -> Result<()> {
+        let running = self.running.clone();
+        running.store(true, Ordering::Relaxed);
+
+        // COM objects stay on the stream thread, only the startup result comes back
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name(format!("wasapi-{name}"))
+            .spawn(move || {
+                // WASAPI requires COM initialized on the calling thread
+                let _ = initialize_mta();
+                if let Err(e) = stream(&running, &ready_tx) {
+                    error!("WASAPI {} loop failed: {:?}", name, e);
+                    let _ = ready_tx.send(Err(e));
+                }
+                running.store(false, Ordering::Relaxed);
+            })?;
+        self.thread = Some(thread);
+
+        match ready_rx.recv() {
+            Ok(result) => result,
+            Err(_) => anyhow::bail!("WASAPI {} thread exited during startup", name),
+        }
+    }
+
+    fn stop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for StreamThread {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Renders frames from a ring to the default WASAPI endpoint.
+pub struct WasapiSink {
+    sample_rate: usize,
+    channels: usize,
+    buffer_frames: usize,
+    stream: StreamThread,
+}
+
+impl WasapiSink {
+    /// Probe the default render endpoint, running it at the first of `rates` it supports.
+    ///
+    /// An empty `rates` lets the device pick from its own supported formats. The
+    /// device is released again; it is reopened on the render thread by [`AudioSink::start`].
+    pub fn new(rates: &[usize], channels: usize) -> Result<Self> {
+        // WASAPI requires COM initialized on the calling thread
+        let _ = initialize_mta();
+
+        let sample_rate = select_sample_rate(rates, channels)?;
+        info!("Render rate: {} Hz", sample_rate);
+
+        let (audio_client, _) = open_render_client(sample_rate, channels)?;
+        let buffer_frames = audio_client.get_buffer_size()? as usize;
+
+        Ok(Self {
+            sample_rate,
+            channels,
+            buffer_frames,
+            stream: StreamThread::new(),
+        })
+    }
+}
+
+impl AudioSink for WasapiSink {
+    fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn block_size(&self) -> usize {
+        self.buffer_frames
+    }
+
+    fn start(&mut self, consumer: FrameRingConsumer) -> Result<()> {
+        let sample_rate = self.sample_rate;
+        let channels = self.channels;
+        self.stream.start("render", move |running, ready| {
+            render(consumer, sample_rate, channels, running, ready)
+        })
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.stream.stop();
+        Ok(())
+    }
+}
+
+fn render(
+    mut consumer: FrameRingConsumer,
+    sample_rate: usize,
+    channels: usize,
+    running: &AtomicBool,
+    ready: &mpsc::Sender<Result<()>>,
+) -> Result<()> {
+    let (audio_client, hw_format) = open_render_client(sample_rate, channels)?;
+
     let render_client = audio_client.get_audiorenderclient()?;
     let event_handle = audio_client.set_get_eventhandle()?;
     let buffer_frames = audio_client.get_buffer_size()? as usize;
@@ -165,113 +392,32 @@ pub fn start_wasapi(
         buffer_frames as f64 / sample_rate as f64 * 1000.0
     );
 
-    // Create rtrb ring buffer
-    let (mut output_producer, mut consumer) = new_framering(channels, buffer_frames * 2, "wasapi");
-
-    let input_rate = 48_000.0;
-    let output_rate = sample_rate as f64;
-    let resample_ratio = output_rate / input_rate; // 4.0
-    let resample_chunk_size = 64;
-    let mut resampler = Async::<f32>::new_sinc(
-        resample_ratio,
-        1.1, // Max ratio relative
-        &SincInterpolationParameters {
-            sinc_len: 64,
-            f_cutoff: 0.95,
-            oversampling_factor: 128,
-            interpolation: rubato::SincInterpolationType::Cubic,
-            window: rubato::WindowFunction::BlackmanHarris2,
-        },
-        resample_chunk_size, // Chunk size
-        channels,            // Number of channels
-        FixedAsync::Output,  // Fixed input size
-    )?;
-
     // Set thread priority to time-critical for audio
     unsafe {
         let _ = SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY_TIME_CRITICAL);
     }
 
-    // Pre-allocate buffers to avoid allocations in the render loop
-    let mut sample_buffer = vec![0.0f32; buffer_frames * channels];
-    let mut byte_buffer = Vec::<u8>::with_capacity(buffer_frames * channels);
+    // Pre-allocate the buffer to avoid allocations in the render loop
+    let block_align = hw_format.get_blockalign() as usize;
+    let mut byte_buffer = Vec::<u8>::with_capacity(buffer_frames * block_align);
+    let stats = consumer.stats();
 
     let bits_per_sample = hw_format.get_bitspersample();
     let is_float = hw_format.get_subformat().ok() == Some(SampleType::Float);
 
     audio_client.start_stream()?;
     info!("Audio stream started");
+    let _ = ready.send(Ok(()));
 
-    // clear out asio buffer
-    while asio_consumer.pop_into(1, vec![0.0].as_mut_slice()) > 0 {}
-
-    // preallocated buffer, we need no more than the wasapi buffer size
-    let mut staging = vec![0.0f32; buffer_frames * channels];
-
-    // Spawn resampler thread
-    thread::spawn(move || {
-        use std::time::Instant;
-        let mut last_log = Instant::now();
-        loop {
-            // Inside the loop, before the availability check
-            if last_log.elapsed().as_millis() >= 1000 {
-                info!(
-                    "ASIO ring: {} frames available, WASAPI ring: {} frames available",
-                    asio_consumer.available_frames(),
-                    output_producer.usage()
-                );
-                last_log = Instant::now();
-            }
-
-            // Accumulate input samples
-            let in_frames = resampler.input_frames_next();
-            let in_samples = in_frames * channels;
-
-            let staging = &mut staging[..in_samples];
-            while asio_consumer.pop_into(in_frames, staging) == 0 {
-                yield_now();
-            }
-
-            // Prepare input slice for resampler
-            let input = match InterleavedSlice::new(staging, channels, in_frames) {
-                Ok(i) => i,
-                Err(e) => {
-                    warn!("Failed to create input slice: {:?}", e);
-                    continue;
-                }
-            };
-
-            // Resample
-            let output = match resampler.process(&input, 0, None) {
-                Ok(o) => o.take_data(),
-                Err(e) => {
-                    warn!("Resampler error: {:?}", e);
-                    continue;
-                }
-            };
-            // Inside the loop, before the availability check
-            if last_log.elapsed().as_millis() >= 1000 {
-                info!(
-                    "ASIO ring: {} frames available, WASAPI ring: {} frames available",
-                    asio_consumer.available_frames(),
-                    output_producer.usage()
-                );
-                last_log = Instant::now();
-            }
-
-            // Push resampled frames into rtrb ring (fast bulk operation!)
-            if !output.is_empty() {
-                output_producer.push(&output);
-            }
-        }
-    });
-
-    // clear out wasapi buffer
-    while consumer.pop_into(1, vec![0.0].as_mut_slice()) > 0 {}
+    // clear out wasapi buffer, without an empty read for the stats to count
+    let stale = consumer.available_frames();
+    if stale > 0 {
+        consumer.read_chunk(stale).commit_all();
+    }
 
     // ===== Render loop =====
-    loop {
-        match event_handle.wait_for_event(INFINITE) {
+    while running.load(Ordering::Relaxed) {
+        match event_handle.wait_for_event(EVENT_TIMEOUT_MS) {
             Ok(_) => Ok(()),
             Err(WasapiError::EventTimeout) => continue,
             Err(e) => Err(e),
@@ -285,27 +431,162 @@ pub fn start_wasapi(
             }
         };
 
-        // Normal render path - pop samples from ring
-        let sample_count = available_frames * channels;
-        let frames_read = consumer.pop_into(available_frames, &mut sample_buffer[..sample_count]);
-
-        // partial or 0 read
-        if frames_read < available_frames {
-            sample_buffer[frames_read * channels..sample_count].fill(0.0); // Fill remaining with silence
+        // Normal render path - convert straight from the ring to hardware format
+        byte_buffer.clear();
+        let chunk = consumer.read_chunk(available_frames);
+        let (first, second) = chunk.as_slices();
+        append_samples_as_bytes(first, &mut byte_buffer, bits_per_sample, is_float);
+        append_samples_as_bytes(second, &mut byte_buffer, bits_per_sample, is_float);
+        let padded = available_frames - chunk.len();
+        chunk.commit_all();
+
+        // only the frames the ring is short of become silence, which is all
+        // zero bytes in every format
+        if padded > 0 {
+            byte_buffer.resize(available_frames * block_align, 0);
+            stats
+                .padded_frames
+                .fetch_add(padded as u64, Ordering::Relaxed);
         }
 
-        // Convert samples to hardware format
-        convert_samples_to_bytes(
-            &sample_buffer[..sample_count],
-            &mut byte_buffer,
-            bits_per_sample,
-            is_float,
-        );
-
         // Write to device
         if let Err(e) = render_client.write_to_device(available_frames, &byte_buffer, None) {
             error!("Failed to write to device: {:?}", e);
             continue;
         }
     }
+
+    audio_client.stop_stream()?;
+    info!("Audio stream stopped");
+    Ok(())
+}
+
+/// Records the default WASAPI capture endpoint, or loops back the default render
+/// endpoint, into a ring.
+pub struct WasapiSource {
+    mode: CaptureMode,
+    sample_rate: usize,
+    channels: usize,
+    buffer_frames: usize,
+    stream: StreamThread,
+}
+
+impl WasapiSource {
+    /// Probe the endpoint for `mode`, capturing at `sample_rate` or its mix format rate.
+    ///
+    /// Shared mode converts from the mix format, so any rate and channel count
+    /// works. The device is released again; it is reopened on the capture thread
+    /// by [`AudioSource::start`].
+    pub fn new(mode: CaptureMode, sample_rate: Option<usize>, channels: usize) -> Result<Self> {
+        // WASAPI requires COM initialized on the calling thread
+        let _ = initialize_mta();
+
+        let sample_rate = match sample_rate {
+            Some(rate) => rate,
+            None => capture_device(mode)?
+                .get_iaudioclient()?
+                .get_mixformat()?
+                .get_samplespersec() as usize,
+        };
+        info!("Capture rate: {} Hz", sample_rate);
+
+        let audio_client = open_capture_client(mode, sample_rate, channels)?;
+        let buffer_frames = audio_client.get_buffer_size()? as usize;
+
+        Ok(Self {
+            mode,
+            sample_rate,
+            channels,
+            buffer_frames,
+            stream: StreamThread::new(),
+        })
+    }
+}
+
+impl AudioSource for WasapiSource {
+    fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn block_size(&self) -> usize {
+        self.buffer_frames
+    }
+
+    fn start(&mut self, producer: FrameRingProducer) -> Result<()> {
+        let mode = self.mode;
+        let sample_rate = self.sample_rate;
+        let channels = self.channels;
+        self.stream.start("capture", move |running, ready| {
+            capture(producer, mode, sample_rate, channels, running, ready)
+        })
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.stream.stop();
+        Ok(())
+    }
+}
+
+fn capture(
+    mut producer: FrameRingProducer,
+    mode: CaptureMode,
+    sample_rate: usize,
+    channels: usize,
+    running: &AtomicBool,
+    ready: &mpsc::Sender<Result<()>>,
+) -> Result<()> {
+    let audio_client = open_capture_client(mode, sample_rate, channels)?;
+
+    let capture_client = audio_client.get_audiocaptureclient()?;
+    let event_handle = audio_client.set_get_eventhandle()?;
+    let buffer_frames = audio_client.get_buffer_size()? as usize;
+
+    info!(
+        "WASAPI capture buffer: {} frames ({}ms)",
+        buffer_frames,
+        buffer_frames as f64 / sample_rate as f64 * 1000.0
+    );
+
+    // Set thread priority to time-critical for audio
+    unsafe {
+        let _ = SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY_TIME_CRITICAL);
+    }
+
+    // Pre-allocate buffers to avoid allocations in the capture loop
+    let mut byte_buffer = vec![0u8; buffer_frames * channels * 4];
+    let mut sample_buffer = vec![0.0f32; buffer_frames * channels];
+
+    audio_client.start_stream()?;
+    info!("Capture stream started");
+    let _ = ready.send(Ok(()));
+
+    // ===== Capture loop =====
+    while running.load(Ordering::Relaxed) {
+        // loopback endpoints only signal while something plays, so drain on timeouts too
+        match event_handle.wait_for_event(EVENT_TIMEOUT_MS) {
+            Ok(()) | Err(WasapiError::EventTimeout) => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        while let Some(1..) = capture_client.get_next_packet_size()? {
+            let (frames, buffer_info) = capture_client.read_from_device(&mut byte_buffer)?;
+            let samples = &mut sample_buffer[..frames as usize * channels];
+            if buffer_info.flags.silent {
+                samples.fill(0.0);
+            } else {
+                for (sample, bytes) in samples.iter_mut().zip(byte_buffer.chunks_exact(4)) {
+                    *sample = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                }
+            }
+            producer.push(samples);
+        }
+    }
+
+    audio_client.stop_stream()?;
+    info!("Capture stream stopped");
+    Ok(())
 }
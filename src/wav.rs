@@ -0,0 +1,863 @@
+use anyhow::{Context, Result};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::backend::{AudioSink, AudioSource};
+use crate::clock::{Clock, Pacing, Timeline};
+use crate::convert::convert_samples_to_bytes;
+use crate::ring::{FrameRingConsumer, FrameRingProducer};
+use crate::util::*;
+
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Frames read or written per period when the caller doesn't pick a size
+const DEFAULT_BLOCK_SIZE: usize = 512;
+
+/// Size of the JUNK chunk reserved up front so the header can become RF64's ds64 in place
+const DS64_SIZE: u32 = 28;
+
+/// Offset of the data chunk's size field in the header written by [`WavWriter`]
+const DATA_SIZE_OFFSET: u64 = 12 + 8 + DS64_SIZE as u64 + 8 + 16 + 4;
+
+/// Sample encodings the WAV backends understand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WavSampleFormat {
+    Int16,
+    Int24,
+    Int32,
+    Float32,
+    Float64,
+}
+
+impl WavSampleFormat {
+    fn from_tag(tag: u16, bits: u16) -> Result<Self> {
+        match (tag, bits) {
+            (WAVE_FORMAT_PCM, 16) => Ok(Self::Int16),
+            (WAVE_FORMAT_PCM, 24) => Ok(Self::Int24),
+            (WAVE_FORMAT_PCM, 32) => Ok(Self::Int32),
+            (WAVE_FORMAT_IEEE_FLOAT, 32) => Ok(Self::Float32),
+            (WAVE_FORMAT_IEEE_FLOAT, 64) => Ok(Self::Float64),
+            _ => anyhow::bail!("unsupported WAV format tag {:#06x} with {} bits", tag, bits),
+        }
+    }
+
+    fn tag(self) -> u16 {
+        match self {
+            Self::Int16 | Self::Int24 | Self::Int32 => WAVE_FORMAT_PCM,
+            Self::Float32 | Self::Float64 => WAVE_FORMAT_IEEE_FLOAT,
+        }
+    }
+
+    pub fn bits_per_sample(self) -> u16 {
+        self.bytes_per_sample() as u16 * 8
+    }
+
+    pub fn is_float(self) -> bool {
+        self.tag() == WAVE_FORMAT_IEEE_FLOAT
+    }
+
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            Self::Int16 => 2,
+            Self::Int24 => 3,
+            Self::Int32 | Self::Float32 => 4,
+            Self::Float64 => 8,
+        }
+    }
+
+    /// Decode little-endian samples of this format into f32
+    fn decode(self, bytes: &[u8], out: &mut [f32]) {
+        let width = self.bytes_per_sample();
+        for (sample, out) in bytes.chunks_exact(width).zip(out.iter_mut()) {
+            *out = match self {
+                Self::Int16 => i16::from_le_bytes([sample[0], sample[1]]) as f32 / 32768.0,
+                Self::Int24 => {
+                    // shift into the top of an i32 to sign-extend
+                    let val = i32::from_le_bytes([0, sample[0], sample[1], sample[2]]) >> 8;
+                    val as f32 / 8_388_608.0
+                }
+                Self::Int32 => {
+                    i32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]) as f32
+                        / 2_147_483_648.0
+                }
+                Self::Float32 => f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]),
+                Self::Float64 => f64::from_le_bytes(sample.try_into().unwrap()) as f32,
+            };
+        }
+    }
+}
+
+/// Layout of the audio in a WAV file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WavSpec {
+    pub sample_rate: usize,
+    pub channels: usize,
+    pub format: WavSampleFormat,
+}
+
+impl WavSpec {
+    pub fn bytes_per_frame(&self) -> usize {
+        self.channels * self.format.bytes_per_sample()
+    }
+}
+
+/// Streams f32 frames out of a RIFF or RF64 WAV file.
+pub struct WavReader<R> {
+    reader: R,
+    spec: WavSpec,
+    data_remaining: u64,
+    bytes: Vec<u8>,
+}
+
+impl WavReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+        Self::new(BufReader::new(file)).with_context(|| format!("reading {}", path.display()))
+    }
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+impl<R: Read + Seek> WavReader<R> {
+    /// Parse the header and leave `reader` positioned at the start of the sample data.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut header = [0u8; 12];
+        reader.read_exact(&mut header)?;
+        let rf64 = match &header[0..4] {
+            b"RIFF" => false,
+            b"RF64" => true,
+            _ => anyhow::bail!("not a RIFF/RF64 file"),
+        };
+        anyhow::ensure!(&header[8..12] == b"WAVE", "not a WAVE file");
+
+        let mut spec = None;
+        let mut ds64_data_size = None;
+        loop {
+            let mut chunk = [0u8; 8];
+            reader
+                .read_exact(&mut chunk)
+                .context("no data chunk in file")?;
+            let size = read_u32(&chunk, 4);
+
+            match &chunk[0..4] {
+                b"ds64" if rf64 => {
+                    let mut body = vec![0u8; size as usize];
+                    reader.read_exact(&mut body)?;
+                    anyhow::ensure!(body.len() >= 16, "truncated ds64 chunk");
+                    ds64_data_size = Some(u64::from_le_bytes(body[8..16].try_into().unwrap()));
+                }
+                b"fmt " => {
+                    let mut body = vec![0u8; size as usize];
+                    reader.read_exact(&mut body)?;
+                    anyhow::ensure!(body.len() >= 16, "truncated fmt chunk");
+                    let mut tag = read_u16(&body, 0);
+                    let channels = read_u16(&body, 2) as usize;
+                    let sample_rate = read_u32(&body, 4) as usize;
+                    let bits = read_u16(&body, 14);
+                    if tag == WAVE_FORMAT_EXTENSIBLE {
+                        anyhow::ensure!(body.len() >= 26, "truncated extensible fmt chunk");
+                        // the sub-format GUID starts with the plain format tag
+                        tag = read_u16(&body, 24);
+                    }
+                    anyhow::ensure!(channels > 0, "WAV file has no channels");
+                    anyhow::ensure!(sample_rate > 0, "WAV file has no sample rate");
+                    spec = Some(WavSpec {
+                        sample_rate,
+                        channels,
+                        format: WavSampleFormat::from_tag(tag, bits)?,
+                    });
+                }
+                b"data" => {
+                    let Some(spec) = spec else {
+                        anyhow::bail!("data chunk before fmt chunk");
+                    };
+                    let data_size = match (size, ds64_data_size) {
+                        (u32::MAX, Some(size)) => size,
+                        _ => size as u64,
+                    };
+                    info!(
+                        "WAV: {} Hz, {} channels, {:?}, {} frames",
+                        spec.sample_rate,
+                        spec.channels,
+                        spec.format,
+                        data_size / spec.bytes_per_frame() as u64
+                    );
+                    return Ok(Self {
+                        reader,
+                        spec,
+                        data_remaining: data_size,
+                        bytes: Vec::new(),
+                    });
+                }
+                _ => {
+                    // chunks are padded to an even length
+                    reader.seek(SeekFrom::Current(size as i64 + (size & 1) as i64))?;
+                }
+            }
+        }
+    }
+
+    pub fn spec(&self) -> WavSpec {
+        self.spec
+    }
+
+    /// Read up to `out.len() / channels` frames, returning how many were read.
+    ///
+    /// Returns 0 at the end of the data.
+    pub fn read_frames(&mut self, out: &mut [f32]) -> Result<usize> {
+        let frame_bytes = self.spec.bytes_per_frame();
+        let frames = (out.len() / self.spec.channels)
+            .min((self.data_remaining / frame_bytes as u64) as usize);
+
+        self.bytes.resize(frames * frame_bytes, 0);
+        self.reader.read_exact(&mut self.bytes)?;
+        self.data_remaining -= self.bytes.len() as u64;
+
+        self.spec
+            .format
+            .decode(&self.bytes, &mut out[..frames * self.spec.channels]);
+        Ok(frames)
+    }
+}
+
+/// Writes f32 frames to a WAV file, switching the header to RF64 if it outgrows 4 GiB.
+///
+/// Samples are encoded with [`convert_samples_to_bytes`], so only the formats a
+/// device can be fed are accepted: 16, 24 and 32 bit int and 32 bit float.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    spec: WavSpec,
+    data_bytes: u64,
+    bytes: Vec<u8>,
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>, spec: WavSpec) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+        Self::new(BufWriter::new(file), spec)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Write a placeholder header; sizes are filled in by [`WavWriter::finalize`].
+    pub fn new(mut writer: W, spec: WavSpec) -> Result<Self> {
+        anyhow::ensure!(
+            spec.format != WavSampleFormat::Float64,
+            "64 bit float WAV output is not supported"
+        );
+
+        let block_align = spec.bytes_per_frame() as u16;
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+        writer.write_all(b"JUNK")?;
+        writer.write_all(&DS64_SIZE.to_le_bytes())?;
+        writer.write_all(&[0u8; DS64_SIZE as usize])?;
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&spec.format.tag().to_le_bytes())?;
+        writer.write_all(&(spec.channels as u16).to_le_bytes())?;
+        writer.write_all(&(spec.sample_rate as u32).to_le_bytes())?;
+        writer.write_all(&(spec.sample_rate as u32 * block_align as u32).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&spec.format.bits_per_sample().to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        Ok(Self {
+            writer,
+            spec,
+            data_bytes: 0,
+            bytes: Vec::new(),
+        })
+    }
+
+    pub fn spec(&self) -> WavSpec {
+        self.spec
+    }
+
+    /// Append interleaved frames; a trailing partial frame is ignored.
+    pub fn write_frames(&mut self, samples: &[f32]) -> Result<()> {
+        let whole = samples.len() - samples.len() % self.spec.channels;
+        convert_samples_to_bytes(
+            &samples[..whole],
+            &mut self.bytes,
+            self.spec.format.bits_per_sample(),
+            self.spec.format.is_float(),
+        );
+        self.writer.write_all(&self.bytes)?;
+        self.data_bytes += self.bytes.len() as u64;
+        Ok(())
+    }
+
+    pub fn frames_written(&self) -> u64 {
+        self.data_bytes / self.spec.bytes_per_frame() as u64
+    }
+
+    /// Patch the chunk sizes and flush, returning the underlying writer.
+    pub fn finalize(mut self) -> Result<W> {
+        // the data chunk is padded to an even length
+        if self.data_bytes & 1 == 1 {
+            self.writer.write_all(&[0])?;
+        }
+        let riff_size = DATA_SIZE_OFFSET - 4 + self.data_bytes.next_multiple_of(2);
+
+        if riff_size <= u32::MAX as u64 {
+            self.writer.seek(SeekFrom::Start(4))?;
+            self.writer.write_all(&(riff_size as u32).to_le_bytes())?;
+            self.writer.seek(SeekFrom::Start(DATA_SIZE_OFFSET))?;
+            self.writer
+                .write_all(&(self.data_bytes as u32).to_le_bytes())?;
+        } else {
+            self.writer.seek(SeekFrom::Start(0))?;
+            self.writer.write_all(b"RF64")?;
+            self.writer.write_all(&u32::MAX.to_le_bytes())?;
+            self.writer.seek(SeekFrom::Start(12))?;
+            self.writer.write_all(b"ds64")?;
+            self.writer.seek(SeekFrom::Current(4))?;
+            self.writer.write_all(&riff_size.to_le_bytes())?;
+            self.writer.write_all(&self.data_bytes.to_le_bytes())?;
+            self.writer
+                .write_all(&self.frames_written().to_le_bytes())?;
+            self.writer.seek(SeekFrom::Start(DATA_SIZE_OFFSET))?;
+            self.writer.write_all(&u32::MAX.to_le_bytes())?;
+        }
+
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Plays a WAV file into the ring, either in real time or as fast as the ring drains.
+pub struct WavSource {
+    path: PathBuf,
+    spec: WavSpec,
+    pacing: Pacing,
+    block_size: usize,
+    running: Arc<AtomicBool>,
+    finished: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl WavSource {
+    pub fn open(path: impl AsRef<Path>, pacing: Pacing) -> Result<Self> {
+        let path = path.as_ref().to_owned();
+        let spec = WavReader::open(&path)?.spec();
+        Ok(Self {
+            path,
+            spec,
+            pacing,
+            block_size: DEFAULT_BLOCK_SIZE,
+            running: Arc::new(AtomicBool::new(false)),
+            finished: Arc::new(AtomicBool::new(false)),
+            thread: None,
+        })
+    }
+
+    /// Frames read from the file per period
+    pub fn with_block_size(mut self, frames: usize) -> Self {
+        self.block_size = frames;
+        self
+    }
+
+    pub fn spec(&self) -> WavSpec {
+        self.spec
+    }
+}
+
+impl AudioSource for WavSource {
+    fn sample_rate(&self) -> usize {
+        self.spec.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.spec.channels
+    }
+
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn start(&mut self, mut ring: FrameRingProducer) -> Result<()> {
+        self.stop()?;
+
+        let mut reader = WavReader::open(&self.path)?;
+        let channels = self.spec.channels;
+        let block_size = self.block_size;
+        let running = self.running.clone();
+        let mut clock =
+            Clock::new(self.pacing.clone(), self.spec.sample_rate).with_running(running.clone());
+        let finished = self.finished.clone();
+        running.store(true, Ordering::Relaxed);
+        finished.store(false, Ordering::Relaxed);
+
+        let thread = thread::Builder::new()
+            .name("wav-source".into())
+            .spawn(move || {
+                let mut block = vec![0.0f32; block_size * channels];
+                clock.reset();
+                while running.load(Ordering::Relaxed) {
+                    let frames = match reader.read_frames(&mut block) {
+                        Ok(0) => break,
+                        Ok(frames) => frames,
+                        Err(e) => {
+                            error!("WAV read failed: {:?}", e);
+                            break;
+                        }
+                    };
+
+                    // without a timeline there is no reason to drop anything
+                    if !clock.is_paced() {
+                        while ring.available_frames() < frames && running.load(Ordering::Relaxed) {
+                            thread::sleep(Duration::from_millis(1));
+                        }
+                    }
+
+                    ring.push(&block[..frames * channels]);
+                    clock.wait(frames);
+                }
+                finished.store(true, Ordering::Relaxed);
+            })?;
+        self.thread = Some(thread);
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        Ok(())
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for WavSource {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+/// Records the frames it is fed to a WAV file, one period per clock tick.
+///
+/// With a paced clock short reads are padded with silence like a device would;
+/// unpaced, the sink waits for every frame and a [`Bridge`](crate::bridge::Bridge)
+/// waits for the sink, so the file matches the stream exactly.
+pub struct WavSink {
+    path: PathBuf,
+    spec: WavSpec,
+    pacing: Pacing,
+    block_size: usize,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Result<u64>>>,
+}
+
+impl WavSink {
+    pub fn create(path: impl AsRef<Path>, spec: WavSpec, pacing: Pacing) -> Result<Self> {
+        let path = path.as_ref().to_owned();
+        // fail early on unwritable paths and unsupported formats
+        WavWriter::create(&path, spec)?.finalize()?;
+        Ok(Self {
+            path,
+            spec,
+            pacing,
+            block_size: DEFAULT_BLOCK_SIZE,
+            running: Arc::new(AtomicBool::new(false)),
+            thread: None,
+        })
+    }
+
+    /// Frames written per period
+    pub fn with_block_size(mut self, frames: usize) -> Self {
+        self.block_size = frames;
+        self
+    }
+
+    pub fn spec(&self) -> WavSpec {
+        self.spec
+    }
+}
+
+impl AudioSink for WavSink {
+    fn sample_rate(&self) -> usize {
+        self.spec.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.spec.channels
+    }
+
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn is_paced(&self) -> bool {
+        self.pacing != Pacing::Unpaced
+    }
+
+    fn timeline(&self) -> Option<Timeline> {
+        self.pacing.timeline()
+    }
+
+    fn start(&mut self, mut ring: FrameRingConsumer) -> Result<()> {
+        self.stop()?;
+
+        let mut writer = WavWriter::create(&self.path, self.spec)?;
+        let channels = self.spec.channels;
+        let block_size = self.block_size;
+        let running = self.running.clone();
+        let mut clock =
+            Clock::new(self.pacing.clone(), self.spec.sample_rate).with_running(running.clone());
+        running.store(true, Ordering::Relaxed);
+
+        let thread = thread::Builder::new()
+            .name("wav-sink".into())
+            .spawn(move || {
+                let mut block = vec![0.0f32; block_size * channels];
+                clock.reset();
+                while running.load(Ordering::Relaxed) {
+                    if clock.is_paced() {
+                        clock.wait(block_size);
+                        // a device can't wait, missing frames become silence
+                        ring.pop_padded_into(block_size, &mut block);
+                    } else if ring.available_frames() < block_size
+                        || ring.pop_into(block_size, &mut block) == 0
+                    {
+                        thread::sleep(Duration::from_millis(1));
+                        continue;
+                    }
+                    writer.write_frames(&block)?;
+                }
+
+                // keep whatever the bridge flushed through before stopping
+                let frame = &mut block[..channels];
+                while ring.available_frames() > 0 && ring.pop_into(1, frame) == 1 {
+                    writer.write_frames(frame)?;
+                }
+
+                let frames = writer.frames_written();
+                writer.finalize()?;
+                Ok(frames)
+            })?;
+        self.thread = Some(thread);
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            match thread.join() {
+                Ok(result) => {
+                    let frames = result?;
+                    info!("Wrote {} frames to {}", frames, self.path.display());
+                }
+                Err(_) => anyhow::bail!("WAV sink thread panicked"),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for WavSink {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bridge::Bridge;
+    use std::io::Cursor;
+    use std::time::Instant;
+
+    /// A test file path no other test uses
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("asio-bridge-{}-{}.wav", std::process::id(), name))
+    }
+
+    /// Distinct samples in -1..1 on every channel
+    fn ramp(frames: usize, channels: usize) -> Vec<f32> {
+        (0..frames * channels)
+            .map(|i| ((i * 7919) % 65_536) as f32 / 32_768.0 - 1.0)
+            .collect()
+    }
+
+    fn write_wav(spec: WavSpec, samples: &[f32]) -> Vec<u8> {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), spec).unwrap();
+        writer.write_frames(samples).unwrap();
+        writer.finalize().unwrap().into_inner()
+    }
+
+    fn read_wav(bytes: Vec<u8>) -> (WavSpec, Vec<f32>) {
+        let mut reader = WavReader::new(Cursor::new(bytes)).unwrap();
+        let spec = reader.spec();
+        let mut samples = Vec::new();
+        let mut block = vec![0.0; 100 * spec.channels];
+        loop {
+            let frames = reader.read_frames(&mut block).unwrap();
+            if frames == 0 {
+                break;
+            }
+            samples.extend_from_slice(&block[..frames * spec.channels]);
+        }
+        (spec, samples)
+    }
+
+    #[test]
+    fn writer_and_reader_round_trip_every_format() {
+        let samples = ramp(1001, 2);
+        for (format, tolerance) in [
+            (WavSampleFormat::Int16, 2.0 / 32_768.0),
+            (WavSampleFormat::Int24, 2.0 / 8_388_608.0),
+            (WavSampleFormat::Int32, 1e-6),
+            (WavSampleFormat::Float32, 0.0),
+        ] {
+            let spec = WavSpec {
+                sample_rate: 48_000,
+                channels: 2,
+                format,
+            };
+            let (read_spec, read) = read_wav(write_wav(spec, &samples));
+            assert_eq!(read_spec, spec);
+            assert_eq!(read.len(), samples.len(), "{:?}", format);
+            for (&a, &b) in samples.iter().zip(&read) {
+                assert!((a - b).abs() <= tolerance, "{:?}: {} vs {}", format, a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn odd_data_is_padded_and_sized() {
+        let spec = WavSpec {
+            sample_rate: 44_100,
+            channels: 1,
+            format: WavSampleFormat::Int24,
+        };
+        let bytes = write_wav(spec, &ramp(3, 1));
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(bytes.len() as u64, DATA_SIZE_OFFSET + 4 + 10);
+        assert_eq!(read_u32(&bytes, 4) as usize, bytes.len() - 8);
+        assert_eq!(read_u32(&bytes, DATA_SIZE_OFFSET as usize), 9);
+        assert_eq!(read_wav(bytes).1.len(), 3);
+    }
+
+    #[test]
+    fn oversized_header_is_patched_to_rf64() {
+        let spec = WavSpec {
+            sample_rate: 48_000,
+            channels: 2,
+            format: WavSampleFormat::Float32,
+        };
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), spec).unwrap();
+        writer.write_frames(&ramp(4, 2)).unwrap();
+        // pretend the data outgrew 4 GiB rather than writing it
+        let data_bytes = 5 << 30;
+        writer.data_bytes = data_bytes;
+        let bytes = writer.finalize().unwrap().into_inner();
+
+        assert_eq!(&bytes[0..4], b"RF64");
+        assert_eq!(read_u32(&bytes, 4), u32::MAX);
+        assert_eq!(&bytes[12..16], b"ds64");
+        let ds64 = |at: usize| u64::from_le_bytes(bytes[20 + at..28 + at].try_into().unwrap());
+        assert_eq!(ds64(0), DATA_SIZE_OFFSET - 4 + data_bytes);
+        assert_eq!(ds64(8), data_bytes);
+        assert_eq!(ds64(16), data_bytes / 8);
+        assert_eq!(read_u32(&bytes, DATA_SIZE_OFFSET as usize), u32::MAX);
+
+        // the reader takes the size from ds64
+        let reader = WavReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(reader.spec(), spec);
+        assert_eq!(reader.data_remaining, data_bytes);
+    }
+
+    /// A RIFF file of `chunks`, each padded to an even length
+    fn riff(chunks: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut body = b"WAVE".to_vec();
+        for (id, chunk) in chunks {
+            body.extend_from_slice(*id);
+            body.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            body.extend_from_slice(chunk);
+            if chunk.len() % 2 == 1 {
+                body.push(0);
+            }
+        }
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    /// The 16 byte body of a `fmt ` chunk
+    fn fmt_chunk(tag: u16, channels: u16, sample_rate: u32, bits: u16) -> Vec<u8> {
+        let block_align = channels * bits / 8;
+        let mut fmt = Vec::new();
+        fmt.extend_from_slice(&tag.to_le_bytes());
+        fmt.extend_from_slice(&channels.to_le_bytes());
+        fmt.extend_from_slice(&sample_rate.to_le_bytes());
+        fmt.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        fmt.extend_from_slice(&block_align.to_le_bytes());
+        fmt.extend_from_slice(&bits.to_le_bytes());
+        fmt
+    }
+
+    fn float_data(samples: &[f32]) -> Vec<u8> {
+        samples.iter().flat_map(|s| s.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn reads_float64() {
+        let samples = [0.5f64, -0.25, 1.0, -1.0, 0.125, 0.0];
+        let data = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let bytes = riff(&[
+            (b"fmt ", fmt_chunk(WAVE_FORMAT_IEEE_FLOAT, 2, 96_000, 64)),
+            (b"data", data),
+        ]);
+
+        let (spec, read) = read_wav(bytes);
+        assert_eq!(
+            spec,
+            WavSpec {
+                sample_rate: 96_000,
+                channels: 2,
+                format: WavSampleFormat::Float64,
+            }
+        );
+        assert_eq!(read, [0.5, -0.25, 1.0, -1.0, 0.125, 0.0]);
+    }
+
+    #[test]
+    fn reads_the_format_out_of_an_extensible_header() {
+        let mut fmt = fmt_chunk(WAVE_FORMAT_EXTENSIBLE, 4, 48_000, 32);
+        fmt.extend_from_slice(&22u16.to_le_bytes()); // extension size
+        fmt.extend_from_slice(&32u16.to_le_bytes()); // valid bits
+        fmt.extend_from_slice(&0x33u32.to_le_bytes()); // FL FR BL BR
+                                                       // KSDATAFORMAT_SUBTYPE_IEEE_FLOAT
+        fmt.extend_from_slice(&[
+            0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38,
+            0x9b, 0x71,
+        ]);
+        let samples = ramp(5, 4);
+        let bytes = riff(&[(b"fmt ", fmt), (b"data", float_data(&samples))]);
+
+        let (spec, read) = read_wav(bytes);
+        assert_eq!(spec.channels, 4);
+        assert_eq!(spec.format, WavSampleFormat::Float32);
+        assert_eq!(read, samples);
+    }
+
+    #[test]
+    fn skips_an_odd_sized_chunk_and_its_pad_byte() {
+        let samples = ramp(3, 1);
+        let bytes = riff(&[
+            (b"fmt ", fmt_chunk(WAVE_FORMAT_IEEE_FLOAT, 1, 44_100, 32)),
+            (b"LIST", b"INFOISFT\x03\x00\x00\x00ab\x00".to_vec()),
+            (b"data", float_data(&samples)),
+        ]);
+        // the LIST chunk's size is odd and the pad byte isn't counted in it
+        assert_eq!(read_u32(&bytes, 40), 15);
+        assert_eq!(&bytes[60..64], b"data");
+
+        assert_eq!(read_wav(bytes).1, samples);
+    }
+
+    /// Run a file through an unpaced bridge and return what the sink recorded
+    fn run_bridge(name: &str, input: &[f32], input_rate: usize, output_rate: usize) -> Vec<f32> {
+        let (source_path, sink_path) = (temp_path(&format!("{name}-in")), temp_path(name));
+        let spec = WavSpec {
+            sample_rate: input_rate,
+            channels: 2,
+            format: WavSampleFormat::Float32,
+        };
+        let mut writer = WavWriter::create(&source_path, spec).unwrap();
+        writer.write_frames(input).unwrap();
+        writer.finalize().unwrap();
+
+        let source = WavSource::open(&source_path, Pacing::Unpaced).unwrap();
+        let sink = WavSink::create(
+            &sink_path,
+            WavSpec {
+                sample_rate: output_rate,
+                ..spec
+            },
+            Pacing::Unpaced,
+        )
+        .unwrap();
+        let mut bridge = Bridge::builder()
+            .source(source)
+            .sink(sink)
+            .drift_compensation(None)
+            .build()
+            .unwrap();
+        bridge.start().unwrap();
+
+        // done once the file is read and nothing moves anymore; a resampler
+        // keeps back whatever is short of its next chunk
+        let deadline = Instant::now() + Duration::from_secs(30);
+        let mut last = None;
+        loop {
+            thread::sleep(Duration::from_millis(50));
+            let status = bridge.status();
+            let progress = (
+                status.source_ring.popped_frames,
+                status.sink_ring.pushed_frames,
+            );
+            if status.source_finished && last == Some(progress) {
+                break;
+            }
+            last = Some(progress);
+            assert!(Instant::now() < deadline, "bridge never drained");
+        }
+        let status = bridge.status();
+        bridge.stop().unwrap();
+        assert_eq!(status.source_ring.dropped_frames, 0);
+        assert_eq!(status.sink_ring.dropped_frames, 0);
+
+        let (_, output) = read_wav(std::fs::read(&sink_path).unwrap());
+        let _ = std::fs::remove_file(source_path);
+        let _ = std::fs::remove_file(sink_path);
+        output
+    }
+
+    #[test]
+    fn unpaced_passthrough_records_the_file_exactly() {
+        let input = ramp(96_000, 2);
+        assert_eq!(run_bridge("passthrough", &input, 48_000, 48_000), input);
+    }
+
+    #[test]
+    fn unpaced_resampling_loses_nothing() {
+        let input = ramp(48_000, 2);
+        let output = run_bridge("sinc", &input, 48_000, 44_100);
+        // everything but what is still inside the filter comes out
+        let expected = 44_100;
+        assert!(
+            output.len() / 2 <= expected && output.len() / 2 > expected - 256,
+            "{} frames",
+            output.len() / 2
+        );
+    }
+}